@@ -21,7 +21,7 @@ fn bench_db_insert(b: &mut Bencher) {
 
     // Initialize a new DB that expects raw bytes for key + value
     // (e.g. DB::new(path, max_level))
-    let mut db = DB::new(wal_path.to_str().unwrap(), 10);
+    let db = DB::new(wal_path.to_str().unwrap(), 10);
     let mut rng = rand::thread_rng();
 
     b.iter(|| {
@@ -43,7 +43,7 @@ fn bench_db_insert_existing(b: &mut Bencher) {
         fs::remove_file(&wal_path).expect("Failed to remove existing WAL file");
     }
 
-    let mut db = DB::new(wal_path.to_str().unwrap(), 10);
+    let db = DB::new(wal_path.to_str().unwrap(), 10);
     let mut rng = rand::thread_rng();
 
     // Pre-populate the DB with 1,000,000 elements
@@ -72,7 +72,7 @@ fn bench_db_get_existing(b: &mut Bencher) {
         fs::remove_file(&wal_path).expect("Failed to remove existing WAL file");
     }
 
-    let mut db = DB::new(wal_path.to_str().unwrap(), 10);
+    let db = DB::new(wal_path.to_str().unwrap(), 10);
     let mut rng = rand::thread_rng();
 
     // We'll store the i32 keys in a Vec so we can retrieve them randomly
@@ -105,7 +105,7 @@ fn bench_db_get_nonexistent(b: &mut Bencher) {
         fs::remove_file(&wal_path).expect("Failed to remove existing WAL file");
     }
 
-    let mut db = DB::new(wal_path.to_str().unwrap(), 10);
+    let db = DB::new(wal_path.to_str().unwrap(), 10);
     let mut rng = rand::thread_rng();
 
     // Pre-populate with 1,000,000 elements
@@ -0,0 +1,712 @@
+use crate::checksum::crc32_update;
+use crate::db::DB;
+use crate::sstable::{self, MANIFEST};
+use crate::wal;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("No backups to restore")]
+    NoBackups,
+    #[error("No backup is from before sequence number {0}")]
+    NoBackupBefore(u64),
+    #[error("Manifest of backup {0} is malformed")]
+    Manifest(u64),
+    #[error("Backup file {0} doesn't match its checksum")]
+    Checksum(String),
+}
+
+/// A backup listed by [`BackupEngine::backups`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupInfo {
+    pub id: u64,
+    /// Size of the WAL the backup restores
+    pub wal_bytes: u64,
+    /// Files the backup is made of, shared with earlier backups where the
+    /// data hadn't changed
+    pub files: usize,
+}
+
+// One file of a backed-up WAL: its name under `shared/`, length and CRC-32
+struct Segment {
+    file: String,
+    len: u64,
+    crc: u32,
+}
+
+// A backed-up SSTable: its file name, sequence and origin in the DB, then
+// the name, length and CRC-32 of its copy under `shared/`
+#[derive(Clone)]
+struct TableCopy {
+    file: String,
+    sequence: u64,
+    ingested: bool,
+    shared: String,
+    len: u64,
+    crc: u32,
+}
+
+// What a backup is made of, stored as text in `meta/<id>`:
+//
+//     wal <bytes> <crc> <flushed>
+//     segment <file> <len> <crc>
+//     ...
+//     table <file> <sequence> <ingest|flush> <shared> <len> <crc>
+//     ...
+//
+// The segments concatenated give the WAL, whose CRC-32 is <crc>; <flushed>
+// is the sequence number of the last write in the tables rather than the WAL.
+struct Manifest {
+    wal_bytes: u64,
+    wal_crc: u32,
+    flushed: u64,
+    segments: Vec<Segment>,
+    tables: Vec<TableCopy>,
+}
+
+/// Full and differential backups of a [`DB`] in a backup directory.
+///
+/// Between flushes the WAL only grows, so a backup whose WAL still starts
+/// with what the previous backup saved copies just the new bytes and shares
+/// the rest.
+/// SSTables never change, so those the previous backup holds are shared
+/// whole. Each backup's manifest records the checksum of every file it uses, and
+/// restoring checks them all.
+pub struct BackupEngine {
+    dir: PathBuf,
+}
+
+impl BackupEngine {
+    /// An engine keeping backups in `dir`, which is created on first backup.
+    pub fn open(dir: impl Into<PathBuf>) -> Self {
+        BackupEngine { dir: dir.into() }
+    }
+
+    /// Backs up `db` into `dest`. Returns the new backup's id.
+    pub fn create(db: &DB, dest: impl Into<PathBuf>) -> Result<u64, BackupError> {
+        Self::open(dest).create_backup(db)
+    }
+
+    /// Restores the latest backup in `dest` as a new WAL at `new_path`,
+    /// which must not exist yet, with its SSTables next to it. Open it with
+    /// `Options::new(new_path)`.
+    pub fn restore(dest: impl Into<PathBuf>, new_path: &Path) -> Result<u64, BackupError> {
+        let engine = Self::open(dest);
+        let id = engine.latest_id()?.ok_or(BackupError::NoBackups)?;
+        engine.restore_backup(id, new_path)?;
+        Ok(id)
+    }
+
+    /// Backs up `db`, copying only what the latest backup doesn't already
+    /// hold. Returns the new backup's id.
+    pub fn create_backup(&self, db: &DB) -> Result<u64, BackupError> {
+        fs::create_dir_all(self.dir.join("meta"))?;
+        fs::create_dir_all(self.dir.join("shared"))?;
+        let (db_manifest, wal) = db.wal_contents()?;
+        let wal_bytes = wal.len() as u64;
+        let mut wal = Cursor::new(wal);
+
+        let mut previous = match self.latest_id()? {
+            Some(id) => Some((id, self.read_manifest(id)?)),
+            None => None,
+        };
+        let id = previous.as_ref().map_or(1, |(id, _)| id + 1);
+        let previous_tables = previous
+            .as_mut()
+            .map(|(_, manifest)| std::mem::take(&mut manifest.tables))
+            .unwrap_or_default();
+
+        // Share the previous backup's files if the WAL still starts with them
+        let mut manifest = Manifest {
+            wal_bytes: 0,
+            wal_crc: 0,
+            flushed: db_manifest.flushed,
+            segments: Vec::new(),
+            tables: Vec::new(),
+        };
+        if let Some((_, previous)) = previous.filter(|(_, m)| m.wal_bytes <= wal_bytes) {
+            let prefix = &mut (&mut wal).take(previous.wal_bytes);
+            let (_, crc) = copy_with_crc(prefix, &mut io::sink(), &mut 0)?;
+            if crc == previous.wal_crc {
+                manifest = Manifest {
+                    flushed: db_manifest.flushed,
+                    ..previous
+                };
+            } else {
+                wal.seek(SeekFrom::Start(0))?;
+            }
+        }
+
+        if manifest.wal_bytes < wal_bytes {
+            let file = format!("{}.seg", id);
+            let path = self.dir.join("shared").join(&file);
+            let mut segment = File::create(&path)?;
+            let mut new = (&mut wal).take(wal_bytes - manifest.wal_bytes);
+            let (len, crc) = copy_with_crc(&mut new, &mut segment, &mut manifest.wal_crc)?;
+            segment.sync_all()?;
+            manifest.wal_bytes += len;
+            manifest.segments.push(Segment { file, len, crc });
+        }
+
+        let data_dir = db.options().data_dir_path();
+        for sstable::TableMeta {
+            file,
+            sequence,
+            ingested,
+        } in db_manifest.tables
+        {
+            let path = data_dir.join(&file);
+            let unchanged = previous_tables
+                .iter()
+                .find(|table| table.file == file)
+                .filter(|table| file_crc(&path).ok() == Some((table.len, table.crc)));
+            let table = match unchanged {
+                Some(table) => TableCopy {
+                    sequence,
+                    ingested,
+                    ..table.clone()
+                },
+                None => {
+                    let shared = format!("{}-{}", id, file);
+                    let mut copy = File::create(self.dir.join("shared").join(&shared))?;
+                    let (len, crc) = copy_with_crc(&mut File::open(&path)?, &mut copy, &mut 0)?;
+                    copy.sync_all()?;
+                    TableCopy {
+                        file,
+                        sequence,
+                        ingested,
+                        shared,
+                        len,
+                        crc,
+                    }
+                }
+            };
+            manifest.tables.push(table);
+        }
+
+        self.write_manifest(id, &manifest)?;
+        Ok(id)
+    }
+
+    /// Restores backup `id` as a new WAL at `new_path`, which must not exist
+    /// yet, checking every file against the manifest on the way. SSTables
+    /// go in `new_path`'s directory, the default data directory.
+    pub fn restore_backup(&self, id: u64, new_path: &Path) -> Result<(), BackupError> {
+        let manifest = self.read_manifest(id)?;
+        self.restore_with_wal(&manifest, new_path, |tmp| self.write_wal(&manifest, tmp))
+    }
+
+    /// Point-in-time restore: restores the newest backup from no later than
+    /// sequence number `until_seq` as a new WAL at `new_path`, like
+    /// [`BackupEngine::restore_backup`], then carries its WAL on with the
+    /// segments [`DB::archive_wal`] shipped to `wal_archive`, up to
+    /// `until_seq` (see [`wal::restore_segments_until`]). Returns the
+    /// sequence number of the last write restored.
+    ///
+    /// `wal_archive` must be the archive of the DB the backups are of. A
+    /// table ingested after the backup isn't in it, nor is the sequence
+    /// number it took, so later writes are counted one short and restoring
+    /// stops at the next flush.
+    pub fn restore_until(
+        &self,
+        wal_archive: &Path,
+        new_path: &Path,
+        until_seq: u64,
+    ) -> Result<u64, BackupError> {
+        let mut manifest = None;
+        for id in self.ids()?.into_iter().rev() {
+            let candidate = self.read_manifest(id)?;
+            if candidate.flushed <= until_seq
+                && candidate
+                    .tables
+                    .iter()
+                    .all(|table| table.sequence <= until_seq)
+            {
+                manifest = Some(candidate);
+                break;
+            }
+        }
+        let manifest = manifest.ok_or(BackupError::NoBackupBefore(until_seq))?;
+        let flushed = manifest.flushed;
+        // The WAL skips the sequence numbers tables ingested since the last
+        // flush took
+        let ingested: Vec<u64> = manifest
+            .tables
+            .iter()
+            .filter(|table| table.ingested && table.sequence > flushed)
+            .map(|table| table.sequence)
+            .collect();
+        let archived = wal::archived_segments(wal_archive)?;
+
+        let mut last = flushed;
+        self.restore_with_wal(&manifest, new_path, |tmp| {
+            let mut backup_wal = OsString::from(tmp.as_os_str());
+            backup_wal.push(".backup");
+            let backup_wal = PathBuf::from(backup_wal);
+            let result = self.write_wal(&manifest, &backup_wal).and_then(|()| {
+                // Start from the backup's WAL or the archived copy of the
+                // same log, whichever got further
+                let mut sources = vec![(flushed, backup_wal.clone())];
+                for (base, path) in archived {
+                    if base == flushed && fs::metadata(&path)?.len() > manifest.wal_bytes {
+                        sources[0].1 = path;
+                    } else if base > flushed {
+                        sources.push((base, path));
+                    }
+                }
+                last = wal::restore_segments_until(&sources, tmp, &ingested, until_seq)?;
+                Ok(())
+            });
+            let _ = fs::remove_file(&backup_wal);
+            result
+        })?;
+        Ok(last)
+    }
+
+    /// Restores the tables `manifest` lists next to `new_path` and the WAL
+    /// `write_wal` writes to the path it's given as `new_path`.
+    fn restore_with_wal(
+        &self,
+        manifest: &Manifest,
+        new_path: &Path,
+        write_wal: impl FnOnce(&Path) -> Result<(), BackupError>,
+    ) -> Result<(), BackupError> {
+        if new_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "restore destination already exists",
+            )
+            .into());
+        }
+
+        let data_dir = match new_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        // Build the WAL under a temporary name and remove whatever was
+        // written if anything fails, so a failed restore leaves nothing behind
+        let mut tmp = OsString::from(new_path.as_os_str());
+        tmp.push(".restoring");
+        let tmp = PathBuf::from(tmp);
+        let mut written = Vec::new();
+        let result = self
+            .write_tables(manifest, data_dir, &mut written)
+            .and_then(|()| {
+                written.push(tmp.clone());
+                write_wal(&tmp)
+            });
+        if result.is_err() {
+            for path in &written {
+                let _ = fs::remove_file(path);
+            }
+        }
+        result?;
+        fs::rename(&tmp, new_path)?;
+        Ok(())
+    }
+
+    /// Lists the backups in the directory, oldest first.
+    pub fn backups(&self) -> Result<Vec<BackupInfo>, BackupError> {
+        self.ids()?
+            .into_iter()
+            .map(|id| {
+                let manifest = self.read_manifest(id)?;
+                Ok(BackupInfo {
+                    id,
+                    wal_bytes: manifest.wal_bytes,
+                    files: manifest.segments.len() + manifest.tables.len(),
+                })
+            })
+            .collect()
+    }
+
+    /// Copies the backup's tables into `dir` and lists them in its
+    /// manifest, pushing each file created onto `written`.
+    fn write_tables(
+        &self,
+        manifest: &Manifest,
+        dir: &Path,
+        written: &mut Vec<PathBuf>,
+    ) -> Result<(), BackupError> {
+        fs::create_dir_all(dir)?;
+        if manifest.tables.is_empty() && manifest.flushed == 0 {
+            return Ok(());
+        }
+        if dir.join(MANIFEST).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "restore directory already holds a DB's tables",
+            )
+            .into());
+        }
+        for table in &manifest.tables {
+            let path = dir.join(&table.file);
+            let mut out = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            written.push(path);
+            let mut copy = File::open(self.dir.join("shared").join(&table.shared))?;
+            if copy_with_crc(&mut copy, &mut out, &mut 0)? != (table.len, table.crc) {
+                return Err(BackupError::Checksum(table.shared.clone()));
+            }
+            out.sync_all()?;
+        }
+        let listed = sstable::Manifest {
+            flushed: manifest.flushed,
+            wal_flushed: 0,
+            tables: manifest
+                .tables
+                .iter()
+                .map(|table| sstable::TableMeta {
+                    file: table.file.clone(),
+                    sequence: table.sequence,
+                    ingested: table.ingested,
+                })
+                .collect(),
+        };
+        written.push(dir.join(MANIFEST));
+        sstable::write_manifest(dir, &listed)?;
+        Ok(())
+    }
+
+    fn write_wal(&self, manifest: &Manifest, path: &Path) -> Result<(), BackupError> {
+        let mut out = File::create(path)?;
+        let mut wal_crc = 0;
+        for segment in &manifest.segments {
+            let mut file = File::open(self.dir.join("shared").join(&segment.file))?;
+            let (len, crc) = copy_with_crc(&mut file, &mut out, &mut wal_crc)?;
+            if len != segment.len || crc != segment.crc {
+                return Err(BackupError::Checksum(segment.file.clone()));
+            }
+        }
+        if wal_crc != manifest.wal_crc {
+            return Err(BackupError::Checksum("WAL".to_string()));
+        }
+        out.sync_all()?;
+        Ok(())
+    }
+
+    fn ids(&self) -> Result<Vec<u64>, BackupError> {
+        let entries = match fs::read_dir(self.dir.join("meta")) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut ids = Vec::new();
+        for entry in entries {
+            // Skips leftover `.tmp` manifests
+            if let Some(id) = entry?.file_name().to_str().and_then(|n| n.parse().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn latest_id(&self) -> Result<Option<u64>, BackupError> {
+        Ok(self.ids()?.last().copied())
+    }
+
+    fn read_manifest(&self, id: u64) -> Result<Manifest, BackupError> {
+        let contents = fs::read_to_string(self.dir.join("meta").join(id.to_string()))?;
+        let mut lines = contents
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>());
+        let malformed = || BackupError::Manifest(id);
+
+        let (wal_bytes, wal_crc, flushed) = match lines.next().as_deref() {
+            Some(["wal", bytes, crc, flushed]) => (
+                bytes.parse().map_err(|_| malformed())?,
+                crc.parse().map_err(|_| malformed())?,
+                flushed.parse().map_err(|_| malformed())?,
+            ),
+            _ => return Err(malformed()),
+        };
+        let mut segments = Vec::new();
+        let mut tables = Vec::new();
+        for line in lines {
+            match line.as_slice() {
+                ["segment", file, len, crc] => segments.push(Segment {
+                    file: file.to_string(),
+                    len: len.parse().map_err(|_| malformed())?,
+                    crc: crc.parse().map_err(|_| malformed())?,
+                }),
+                ["table", file, sequence, origin, shared, len, crc] => tables.push(TableCopy {
+                    file: file.to_string(),
+                    sequence: sequence.parse().map_err(|_| malformed())?,
+                    ingested: match *origin {
+                        "ingest" => true,
+                        "flush" => false,
+                        _ => return Err(malformed()),
+                    },
+                    shared: shared.to_string(),
+                    len: len.parse().map_err(|_| malformed())?,
+                    crc: crc.parse().map_err(|_| malformed())?,
+                }),
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(Manifest {
+            wal_bytes,
+            wal_crc,
+            flushed,
+            segments,
+            tables,
+        })
+    }
+
+    /// Writes the manifest via a synced temp file and a rename, so a backup
+    /// only shows up once it's complete.
+    fn write_manifest(&self, id: u64, manifest: &Manifest) -> io::Result<()> {
+        let path = self.dir.join("meta").join(id.to_string());
+        let tmp = path.with_extension("tmp");
+        {
+            let mut f = File::create(&tmp)?;
+            writeln!(
+                f,
+                "wal {} {} {}",
+                manifest.wal_bytes, manifest.wal_crc, manifest.flushed
+            )?;
+            for segment in &manifest.segments {
+                writeln!(
+                    f,
+                    "segment {} {} {}",
+                    segment.file, segment.len, segment.crc
+                )?;
+            }
+            for table in &manifest.tables {
+                writeln!(
+                    f,
+                    "table {} {} {} {} {} {}",
+                    table.file,
+                    table.sequence,
+                    if table.ingested { "ingest" } else { "flush" },
+                    table.shared,
+                    table.len,
+                    table.crc
+                )?;
+            }
+            f.sync_all()?;
+        }
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Length and CRC-32 of the file at `path`.
+fn file_crc(path: &Path) -> io::Result<(u64, u32)> {
+    copy_with_crc(&mut File::open(path)?, &mut io::sink(), &mut 0)
+}
+
+/// Copies `reader` to `writer`, returning the bytes copied and their CRC-32.
+/// `running` is also updated with them, for a CRC over several copies.
+fn copy_with_crc(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    running: &mut u32,
+) -> io::Result<(u64, u32)> {
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut len = 0;
+    let mut crc = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok((len, crc)),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buf[..n])?;
+        crc = crc32_update(crc, &buf[..n]);
+        *running = crc32_update(*running, &buf[..n]);
+        len += n as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackupEngine, BackupError};
+    use crate::db::DB;
+    use crate::options::Options;
+    use crate::sstable::SSTableBuilder;
+    use crate::write_batch::WriteBatch;
+    use std::fs;
+
+    #[test]
+    fn test_differential_backup_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let db = DB::new(dir.path().join("db.wal").to_str().unwrap(), 5);
+
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        assert_eq!(BackupEngine::create(&db, &backups).unwrap(), 1);
+        db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+        assert_eq!(BackupEngine::create(&db, &backups).unwrap(), 2);
+
+        // The second backup shares the first one's file and adds the new bytes
+        let listed = BackupEngine::open(&backups).backups().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!((listed[0].files, listed[1].files), (1, 2));
+        assert_eq!(listed[1].wal_bytes, db.wal_offset());
+
+        let latest = dir.path().join("latest.wal");
+        assert_eq!(BackupEngine::restore(&backups, &latest).unwrap(), 2);
+        let restored = DB::open(Options::new(&latest)).unwrap();
+        assert_eq!(restored.get(b"b").unwrap(), b"2".to_vec());
+        assert!(BackupEngine::restore(&backups, &latest).is_err());
+
+        let first = dir.path().join("first.wal");
+        BackupEngine::open(&backups)
+            .restore_backup(1, &first)
+            .unwrap();
+        let restored = DB::open(Options::new(&first)).unwrap();
+        assert_eq!(restored.get(b"a").unwrap(), b"1".to_vec());
+        assert!(restored.get(b"b").is_err());
+    }
+
+    #[test]
+    fn test_restore_until() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let archive = dir.path().join("archive");
+        let options = Options::new(dir.path().join("db.wal")).keep_wal_segments(true);
+        let db = DB::open(options).unwrap();
+        let put = |key: &[u8]| db.put(key.to_vec(), key.to_vec()).unwrap();
+
+        put(b"k1");
+        put(b"k2");
+        put(b"k3");
+        BackupEngine::create(&db, &backups).unwrap();
+        db.archive_wal(&archive).unwrap();
+        put(b"k4");
+        put(b"k5");
+        db.flush().unwrap();
+        BackupEngine::create(&db, &backups).unwrap();
+        put(b"k6");
+        let mut batch = WriteBatch::new();
+        batch.put(b"k7".to_vec(), b"k7".to_vec());
+        batch.put(b"k8".to_vec(), b"k8".to_vec());
+        db.write(batch).unwrap();
+        put(b"k9");
+        db.archive_wal(&archive).unwrap();
+
+        let engine = BackupEngine::open(&backups);
+        let restore = |name: &str, until_seq| {
+            let path = dir.path().join(name).join("db.wal");
+            let last = engine.restore_until(&archive, &path, until_seq).unwrap();
+            let restored = DB::open(Options::new(&path)).unwrap();
+            let keys: Vec<_> = restored.iter().map(|(key, _)| key).collect();
+            (last, keys)
+        };
+        let keys = |n: usize| {
+            (1..=n)
+                .map(|i| format!("k{}", i).into_bytes())
+                .collect::<Vec<_>>()
+        };
+
+        // The second backup has k5 flushed, so this goes from the first and
+        // the archived segment it's the start of
+        assert_eq!(restore("4", 4), (4, keys(4)));
+        // The batch holding 7 and 8 is restored whole or not at all
+        assert_eq!(restore("7", 7), (6, keys(6)));
+        assert_eq!(restore("all", 100), (9, keys(9)));
+
+        let empty = BackupEngine::open(dir.path().join("none"));
+        assert!(matches!(
+            empty.restore_until(&archive, &dir.path().join("none.wal"), 4),
+            Err(BackupError::NoBackupBefore(4))
+        ));
+    }
+
+    #[test]
+    fn test_backup_shares_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let db = DB::new(dir.path().join("db/db.wal").to_str().unwrap(), 5);
+        let sst = dir.path().join("bulk.sst");
+        let mut builder = SSTableBuilder::new(&sst).unwrap();
+        builder.add(b"a", b"1").unwrap();
+        builder.finish().unwrap();
+        db.ingest_sstable(&sst).unwrap();
+
+        BackupEngine::create(&db, &backups).unwrap();
+        db.put(b"b".to_vec(), b"2".to_vec()).unwrap();
+        BackupEngine::create(&db, &backups).unwrap();
+        let listed = BackupEngine::open(&backups).backups().unwrap();
+        assert_eq!((listed[0].files, listed[1].files), (1, 2));
+        assert_eq!(fs::read_dir(backups.join("shared")).unwrap().count(), 2);
+
+        let restored = dir.path().join("restored/db.wal");
+        BackupEngine::restore(&backups, &restored).unwrap();
+        let restored = DB::open(Options::new(&restored)).unwrap();
+        assert_eq!(restored.get(b"a").unwrap(), b"1".to_vec());
+        assert_eq!(restored.get(b"b").unwrap(), b"2".to_vec());
+    }
+
+    #[test]
+    fn test_backup_while_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let db = DB::new(dir.path().join("db.wal").to_str().unwrap(), 5);
+        let engine = BackupEngine::open(&backups);
+
+        let mut ids = Vec::new();
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                for i in 0u32..2000 {
+                    db.put(i.to_be_bytes().to_vec(), b"v".to_vec()).unwrap();
+                    if i % 100 == 99 {
+                        db.flush().unwrap();
+                    }
+                }
+            });
+            while !writer.is_finished() {
+                ids.push(engine.create_backup(&db).unwrap());
+            }
+        });
+
+        // Each backup holds the writes up to some point, none missing or torn
+        for id in ids {
+            let target = dir.path().join(format!("restored-{}/db.wal", id));
+            engine.restore_backup(id, &target).unwrap();
+            let restored = DB::open(Options::new(&target)).unwrap();
+            let keys: Vec<Vec<u8>> = restored.iter().map(|(key, _)| key).collect();
+            let expected: Vec<Vec<u8>> = (0u32..keys.len() as u32)
+                .map(|i| i.to_be_bytes().to_vec())
+                .collect();
+            assert_eq!(keys, expected, "backup {}", id);
+        }
+    }
+
+    #[test]
+    fn test_restore_checks_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let db = DB::new(dir.path().join("db.wal").to_str().unwrap(), 5);
+        db.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        BackupEngine::create(&db, &backups).unwrap();
+
+        let segment = backups.join("shared/1.seg");
+        let mut data = fs::read(&segment).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+        fs::write(&segment, data).unwrap();
+
+        let target = dir.path().join("restored.wal");
+        assert!(matches!(
+            BackupEngine::restore(&backups, &target),
+            Err(BackupError::Checksum(_))
+        ));
+        assert!(!target.exists());
+        assert!(matches!(
+            BackupEngine::restore(dir.path().join("none"), &target),
+            Err(BackupError::NoBackups)
+        ));
+    }
+}
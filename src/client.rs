@@ -1,9 +1,29 @@
-use crate::db::DB;
-use std::io::{self, Write};
+use crate::db::{DatabaseError, DB};
+use crate::options::Options;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+// Request opcodes for the framed protocol
+const OP_GET: u8 = b'G';
+const OP_SET: u8 = b'S';
+
+// Response status bytes for the framed protocol
+const STATUS_OK: u8 = 0;
+const STATUS_NOT_FOUND: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+/// The largest frame [`read_frame`] accepts.
+pub const MAX_FRAME_LEN: usize = 64 << 20;
 
 pub fn start() {
-    // Adjust as needed: DB::new likely takes (filename, max_level) or similar
-    let mut db = DB::new("db.wal", 5);
+    let mut db = match DB::open(Options::new("db.wal").max_level(5)) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return;
+        }
+    };
+    // Interactive use is slow enough to count every access
+    db.enable_hot_key_sampling(1.0, 10);
     let stdin = io::stdin();
 
     loop {
@@ -44,10 +64,7 @@ pub fn start() {
                     println!("Usage: get <key>");
                     continue;
                 }
-                // Convert the typed key to raw bytes
-                let key_bytes = tokens[1].as_bytes().to_vec();
-
-                match db.get(key_bytes) {
+                match db.get(tokens[1].as_bytes()) {
                     Ok(value_bytes) => {
                         // If you want to interpret them as UTF-8, do so:
                         match String::from_utf8(value_bytes) {
@@ -80,11 +97,235 @@ pub fn start() {
                 }
             }
 
+            "del" | "delete" => {
+                if tokens.len() < 2 {
+                    eprintln!("Usage: del <key>");
+                    continue;
+                }
+                match db.delete(tokens[1].as_bytes().to_vec()) {
+                    Ok(_) => println!("OK"),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            }
+
+            "verify" => {
+                let report = db.verify();
+                if report.is_ok() {
+                    println!("OK");
+                } else {
+                    eprintln!("Verification failed: {:?}", report);
+                }
+            }
+
+            "checksum" => {
+                if tokens.len() < 3 {
+                    eprintln!("Usage: checksum <start> <end>");
+                    continue;
+                }
+                let digest = db.checksum_range(tokens[1].as_bytes(), tokens[2].as_bytes());
+                println!("{:08x}", digest);
+            }
+
+            "prefixes" => {
+                // Defaults to ':'-delimited prefixes, sampling 1000 keys
+                let delimiter = tokens.get(1).and_then(|d| d.bytes().next()).unwrap_or(b':');
+                for stats in db.prefix_stats(delimiter, 1000) {
+                    println!(
+                        "{} keys={} bytes={}",
+                        String::from_utf8_lossy(&stats.prefix),
+                        stats.approx_keys,
+                        stats.approx_bytes
+                    );
+                }
+            }
+
+            "hotkeys" => {
+                for (key, count) in db.hot_keys() {
+                    println!("{} {}", String::from_utf8_lossy(&key), count);
+                }
+            }
+
             // Unknown command
             _ => {
                 eprintln!("Unknown command: {}", command);
-                eprintln!("Commands: get <key>, set <key> <value>, quit, exit");
+                eprintln!("Commands: get <key>, set <key> <value>, del <key>, checksum <start> <end>, prefixes [delimiter], hotkeys, verify, quit, exit");
             }
         }
     }
+
+    if let Err(e) = db.close() {
+        eprintln!("Error: {}", e);
+    }
+}
+
+/// Serves the framed protocol over stdin/stdout until stdin closes.
+pub fn serve_stdio() -> io::Result<()> {
+    let db = DB::open(Options::new("db.wal").max_level(5)).map_err(io::Error::other)?;
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    serve_framed(
+        &db,
+        BufReader::new(stdin.lock()),
+        BufWriter::new(stdout.lock()),
+    )
+}
+
+/// Answers length-prefixed requests from `input` until it ends.
+///
+/// Every frame is a big-endian `u32` length followed by that many bytes, so
+/// keys and values pass through byte for byte. A request frame is an opcode
+/// followed by length-prefixed fields:
+///
+/// * `G <key>` gets a key
+/// * `S <key> <value>` sets a key
+///
+/// Each request gets one response frame: a status byte (0 ok, 1 not found,
+/// 2 error) and one length-prefixed field holding the value for a get, the
+/// error message for an error, or nothing.
+pub fn serve_framed<R: Read, W: Write>(db: &DB, mut input: R, mut output: W) -> io::Result<()> {
+    while let Some(request) = read_frame(&mut input)? {
+        let (status, payload) = match handle_request(db, &request) {
+            Ok(value) => (STATUS_OK, value),
+            Err(DatabaseError::KeyNotFound) => (STATUS_NOT_FOUND, Vec::new()),
+            Err(e) => (STATUS_ERROR, e.to_string().into_bytes()),
+        };
+        let mut response = vec![status];
+        push_field(&mut response, &payload);
+        write_frame(&mut output, &response)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn handle_request(db: &DB, request: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+    let malformed = || DatabaseError::Protocol("malformed request".to_string());
+    let (&op, mut rest) = request.split_first().ok_or_else(malformed)?;
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        let (len, tail) = rest.split_first_chunk::<4>().ok_or_else(malformed)?;
+        let len = u32::from_be_bytes(*len) as usize;
+        if tail.len() < len {
+            return Err(malformed());
+        }
+        let (field, tail) = tail.split_at(len);
+        fields.push(field);
+        rest = tail;
+    }
+
+    match (op, fields.as_slice()) {
+        (OP_GET, [key]) => db.get(key),
+        (OP_SET, [key, value]) => db.put(key.to_vec(), value.to_vec()).map(|_| Vec::new()),
+        _ => Err(DatabaseError::Protocol(format!(
+            "unknown request {:?} with {} fields",
+            op as char,
+            fields.len()
+        ))),
+    }
+}
+
+/// Appends `data` to `buf` as a length-prefixed field.
+pub fn push_field(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Reads one length-prefixed frame, or `None` if the input ends before it.
+///
+/// Input ending part-way through a frame is an `UnexpectedEof` error, and a
+/// length over [`MAX_FRAME_LEN`] is `InvalidData`, so a bad prefix can't
+/// make us allocate gigabytes.
+pub fn read_frame<R: Read>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    let mut filled = 0;
+    while filled < len.len() {
+        match input.read(&mut len[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "input ended inside a frame's length",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes is over the {} byte limit",
+                len, MAX_FRAME_LEN
+            ),
+        ));
+    }
+    let mut frame = vec![0u8; len];
+    input.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+pub fn write_frame<W: Write>(output: &mut W, frame: &[u8]) -> io::Result<()> {
+    output.write_all(&(frame.len() as u32).to_be_bytes())?;
+    output.write_all(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{push_field, read_frame, serve_framed, write_frame};
+    use crate::db::DB;
+    use std::io::{self, Cursor};
+
+    fn request(op: u8, fields: &[&[u8]]) -> Vec<u8> {
+        let mut body = vec![op];
+        for field in fields {
+            push_field(&mut body, field);
+        }
+        let mut frame = Vec::new();
+        write_frame(&mut frame, &body).unwrap();
+        frame
+    }
+
+    #[test]
+    fn test_serve_framed_is_binary_safe() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DB::new(dir.path().join("db.wal").to_str().unwrap(), 5);
+
+        let key: &[u8] = b"key with spaces\n\0";
+        let value: &[u8] = &[0, 255, b' ', b'\n', 7];
+        let mut input = request(b'S', &[key, value]);
+        input.extend(request(b'G', &[key]));
+        input.extend(request(b'G', &[b"missing"]));
+        input.extend(request(b'X', &[]));
+
+        let mut output = Vec::new();
+        serve_framed(&db, Cursor::new(input), &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut responses = Vec::new();
+        while let Some(frame) = read_frame(&mut output).unwrap() {
+            responses.push(frame);
+        }
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0], vec![0, 0, 0, 0, 0]);
+        assert_eq!(responses[1][..5], [0, 0, 0, 0, 5]);
+        assert_eq!(&responses[1][5..], value);
+        assert_eq!(responses[2][0], 1);
+        assert_eq!(responses[3][0], 2);
+    }
+
+    #[test]
+    fn test_read_frame_rejects_bad_input() {
+        // A truncated length prefix isn't a clean end of input
+        let err = read_frame(&mut Cursor::new(vec![0, 0])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        // Nor is a truncated body
+        let err = read_frame(&mut Cursor::new(vec![0, 0, 0, 4, 1])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        // A huge length fails before anything is allocated
+        let err = read_frame(&mut Cursor::new(vec![0xFF; 4])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(read_frame(&mut Cursor::new(Vec::new())).unwrap().is_none());
+    }
 }
@@ -0,0 +1,135 @@
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 1024;
+
+/// Approximate top-K tracker for frequently accessed keys.
+///
+/// A sampled fraction of accesses is counted in a count-min sketch, and the
+/// `k` keys with the highest estimated counts are kept as candidates.
+pub struct HotKeys {
+    sample_rate: f64,
+    k: usize,
+    // SKETCH_DEPTH rows of SKETCH_WIDTH counters
+    sketch: Vec<u32>,
+    top: HashMap<Vec<u8>, u64>,
+    rng: SmallRng,
+}
+
+impl HotKeys {
+    /// Tracks the `k` hottest keys, counting `sample_rate` (0.0..=1.0) of accesses.
+    pub fn new(sample_rate: f64, k: usize) -> Self {
+        HotKeys {
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            k,
+            sketch: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+            top: HashMap::new(),
+            rng: SmallRng::from_entropy(),
+        }
+    }
+
+    /// Records one access to `key`, if it is sampled.
+    pub fn record(&mut self, key: &[u8]) {
+        if self.k == 0 || !self.rng.gen_bool(self.sample_rate) {
+            return;
+        }
+
+        // The estimate is the smallest counter the key hashes to
+        let mut estimate = u64::MAX;
+        for row in 0..SKETCH_DEPTH {
+            let idx = row * SKETCH_WIDTH + slot(row, key);
+            self.sketch[idx] = self.sketch[idx].saturating_add(1);
+            estimate = estimate.min(self.sketch[idx] as u64);
+        }
+
+        if let Some(count) = self.top.get_mut(key) {
+            *count = estimate;
+            return;
+        }
+        if self.top.len() < self.k {
+            self.top.insert(key.to_vec(), estimate);
+            return;
+        }
+
+        // Replace the coldest candidate if this key has overtaken it
+        let Some(cold_count) = self.top.values().min().copied() else {
+            return;
+        };
+        if estimate > cold_count {
+            let coldest = self
+                .top
+                .iter()
+                .find(|(_, count)| **count == cold_count)
+                .map(|(key, _)| key.clone());
+            if let Some(coldest) = coldest {
+                self.top.remove(&coldest);
+            }
+            self.top.insert(key.to_vec(), estimate);
+        }
+    }
+
+    /// Returns the hottest keys, hottest first, with their estimated access
+    /// counts scaled back up by the sample rate.
+    pub fn top(&self) -> Vec<(Vec<u8>, u64)> {
+        let mut top: Vec<(Vec<u8>, u64)> = self
+            .top
+            .iter()
+            .map(|(key, &count)| (key.clone(), (count as f64 / self.sample_rate) as u64))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top
+    }
+}
+
+fn slot(row: usize, key: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+    row.hash(&mut hasher);
+    key.hash(&mut hasher);
+    (hasher.finish() % SKETCH_WIDTH as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HotKeys;
+
+    #[test]
+    fn test_tracks_hottest_keys() {
+        let mut hot = HotKeys::new(1.0, 2);
+        for (key, times) in [(&b"a"[..], 50), (b"b", 30), (b"c", 10), (b"d", 1)] {
+            for _ in 0..times {
+                hot.record(key);
+            }
+        }
+
+        let top = hot.top();
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0, b"a".to_vec());
+        assert_eq!(top[1].0, b"b".to_vec());
+        // Count-min only ever overestimates
+        assert!(top[0].1 >= 50);
+        assert!(top[1].1 >= 30);
+    }
+
+    #[test]
+    fn test_late_hot_key_displaces_cold_one() {
+        let mut hot = HotKeys::new(1.0, 1);
+        hot.record(b"early");
+        for _ in 0..5 {
+            hot.record(b"late");
+        }
+        assert_eq!(hot.top()[0].0, b"late".to_vec());
+    }
+
+    #[test]
+    fn test_zero_sample_rate_records_nothing() {
+        let mut hot = HotKeys::new(0.0, 10);
+        for _ in 0..100 {
+            hot.record(b"key");
+        }
+        assert!(hot.top().is_empty());
+    }
+}
@@ -0,0 +1,720 @@
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::fmt::Display;
+use thiserror::Error;
+
+// An order-preserving ("memcomparable") encoding of serde types, so that
+// comparing encoded keys byte by byte orders them like the values they stand
+// for, field by field:
+//
+//     bool, unsigned ints    big-endian
+//     signed ints            big-endian with the sign bit flipped
+//     floats                 big-endian bits, all flipped if negative and
+//                            only the sign bit otherwise (NaNs sort last)
+//     char                   as a u32
+//     str, bytes             0x00 escaped as 0x00 0xFF, then 0x00 0x00
+//     Option, seq, map       0 for None/the end, 1 before each value/element
+//     enum variants          index as a u32, then any fields
+//     structs, tuples        fields in order; unit types are empty
+//
+// Every encoding is self-delimiting, so one is never a prefix of another
+// that sorts differently.
+
+#[derive(Error, Debug)]
+pub enum KeyEncodingError {
+    #[error("{0}")]
+    Message(String),
+    #[error("Key ended part-way through a value")]
+    Eof,
+    #[error("Key has {0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("Key encoding isn't self-describing, so it can't be decoded as `{0}`")]
+    NotSelfDescribing(&'static str),
+    #[error("Malformed key: {0}")]
+    Malformed(&'static str),
+}
+
+impl ser::Error for KeyEncodingError {
+    fn custom<T: Display>(msg: T) -> Self {
+        KeyEncodingError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for KeyEncodingError {
+    fn custom<T: Display>(msg: T) -> Self {
+        KeyEncodingError::Message(msg.to_string())
+    }
+}
+
+type Result<T> = std::result::Result<T, KeyEncodingError>;
+
+/// Encodes `key` so encoded keys sort like the values they came from, e.g.
+/// `-1i32` before `1i32`, or `("a", 2)` before `("b", 1)`.
+///
+/// ```
+/// use kv_db::key_encoding::encode;
+///
+/// assert!(encode(&-1i64).unwrap() < encode(&1i64).unwrap());
+/// assert!(encode(&("a", 2)).unwrap() < encode(&("ab", 1)).unwrap());
+/// ```
+pub fn encode<K: Serialize + ?Sized>(key: &K) -> Result<Vec<u8>> {
+    let mut serializer = KeySerializer { out: Vec::new() };
+    key.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+/// Decodes a key written by [`encode`].
+pub fn decode<K: DeserializeOwned>(bytes: &[u8]) -> Result<K> {
+    let mut deserializer = KeyDeserializer { input: bytes };
+    let key = K::deserialize(&mut deserializer)?;
+    match deserializer.input.len() {
+        0 => Ok(key),
+        n => Err(KeyEncodingError::TrailingBytes(n)),
+    }
+}
+
+struct KeySerializer {
+    out: Vec<u8>,
+}
+
+impl KeySerializer {
+    fn put_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.out.push(byte);
+            if byte == 0 {
+                self.out.push(0xFF);
+            }
+        }
+        self.out.extend_from_slice(&[0, 0]);
+    }
+}
+
+impl ser::Serializer for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyEncodingError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.out.push(u8::from(v));
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_u8(v as u8 ^ 0x80)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_u16(v as u16 ^ (1 << 15))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_u32(v as u32 ^ (1 << 31))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.serialize_u64(v as u64 ^ (1 << 63))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.serialize_u128(v as u128 ^ (1 << 127))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.out.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.out.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        let bits = v.to_bits();
+        let flip = if bits >> 31 == 1 { u32::MAX } else { 1 << 31 };
+        self.serialize_u32(bits ^ flip)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        let bits = v.to_bits();
+        let flip = if bits >> 63 == 1 { u64::MAX } else { 1 << 63 };
+        self.serialize_u64(bits ^ flip)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.put_bytes(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.put_bytes(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.serialize_u8(0)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        self.out.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.serialize_u32(variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.serialize_u32(variant_index)?;
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyEncodingError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.out.push(1);
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.out.push(0);
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut KeySerializer {
+    type Ok = ();
+    type Error = KeyEncodingError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.out.push(1);
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        self.out.push(0);
+        Ok(())
+    }
+}
+
+// Fixed-length compounds are just their fields in order
+macro_rules! fields_in_order {
+    ($trait:ident, $method:ident $(, $key:ident)?) => {
+        impl ser::$trait for &mut KeySerializer {
+            type Ok = ();
+            type Error = KeyEncodingError;
+
+            fn $method<T: Serialize + ?Sized>(
+                &mut self,
+                $($key: &'static str,)?
+                value: &T,
+            ) -> Result<()> {
+                value.serialize(&mut **self)
+            }
+
+            fn end(self) -> Result<()> {
+                Ok(())
+            }
+        }
+    };
+}
+
+fields_in_order!(SerializeTuple, serialize_element);
+fields_in_order!(SerializeTupleStruct, serialize_field);
+fields_in_order!(SerializeTupleVariant, serialize_field);
+fields_in_order!(SerializeStruct, serialize_field, _key);
+fields_in_order!(SerializeStructVariant, serialize_field, _key);
+
+struct KeyDeserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> KeyDeserializer<'de> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let (bytes, rest) = self
+            .input
+            .split_first_chunk::<N>()
+            .ok_or(KeyEncodingError::Eof)?;
+        self.input = rest;
+        Ok(*bytes)
+    }
+
+    fn flag(&mut self) -> Result<bool> {
+        match self.take::<1>()? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(KeyEncodingError::Malformed("expected a 0 or 1 flag byte")),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_be_bytes(self.take()?))
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        loop {
+            match self.take::<1>()? {
+                [0] => match self.take::<1>()? {
+                    [0] => return Ok(out),
+                    [0xFF] => out.push(0),
+                    _ => return Err(KeyEncodingError::Malformed("bad escape in string")),
+                },
+                [byte] => out.push(byte),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        String::from_utf8(self.bytes()?)
+            .map_err(|_| KeyEncodingError::Malformed("string isn't UTF-8"))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut KeyDeserializer<'de> {
+    type Error = KeyEncodingError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(KeyEncodingError::NotSelfDescribing("any"))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(KeyEncodingError::NotSelfDescribing("ignored_any"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_bool(self.flag()?)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8((self.take::<1>()?[0] ^ 0x80) as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i16((u16::from_be_bytes(self.take()?) ^ (1 << 15)) as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i32((self.u32()? ^ (1 << 31)) as i32)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i64((self.u64()? ^ (1 << 63)) as i64)
+    }
+
+    fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i128((u128::from_be_bytes(self.take()?) ^ (1 << 127)) as i128)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.take::<1>()?[0])
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u16(u16::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.u32()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(self.u64()?)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u128(u128::from_be_bytes(self.take()?))
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bits = self.u32()?;
+        let flip = if bits >> 31 == 1 { 1 << 31 } else { u32::MAX };
+        visitor.visit_f32(f32::from_bits(bits ^ flip))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bits = self.u64()?;
+        let flip = if bits >> 63 == 1 { 1 << 63 } else { u64::MAX };
+        visitor.visit_f64(f64::from_bits(bits ^ flip))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let c = char::from_u32(self.u32()?).ok_or(KeyEncodingError::Malformed("invalid char"))?;
+        visitor.visit_char(c)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.flag()? {
+            true => visitor.visit_some(self),
+            false => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Flagged(self))
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(Flagged(self))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Fields(self, len))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Fields(self, len))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Fields(self, fields.len()))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(KeyEncodingError::NotSelfDescribing("identifier"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// A seq or map: each element preceded by a 1, then a 0
+struct Flagged<'a, 'de>(&'a mut KeyDeserializer<'de>);
+
+impl<'de> de::SeqAccess<'de> for Flagged<'_, 'de> {
+    type Error = KeyEncodingError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.0.flag()? {
+            true => seed.deserialize(&mut *self.0).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Flagged<'_, 'de> {
+    type Error = KeyEncodingError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.0.flag()? {
+            true => seed.deserialize(&mut *self.0).map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.0)
+    }
+}
+
+// A tuple or struct: its fields in order, with nothing between them
+struct Fields<'a, 'de>(&'a mut KeyDeserializer<'de>, usize);
+
+impl<'de> de::SeqAccess<'de> for Fields<'_, 'de> {
+    type Error = KeyEncodingError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.1 == 0 {
+            return Ok(None);
+        }
+        self.1 -= 1;
+        seed.deserialize(&mut *self.0).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.1)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut KeyDeserializer<'de> {
+    type Error = KeyEncodingError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let index: de::value::U32Deserializer<KeyEncodingError> = self.u32()?.into_deserializer();
+        Ok((seed.deserialize(index)?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut KeyDeserializer<'de> {
+    type Error = KeyEncodingError;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Fields(self, len))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(Fields(self, fields.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, KeyEncodingError};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+    enum Kind {
+        Unit,
+        Named { id: u16 },
+        Pair(i8, Option<String>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
+    struct Key {
+        tenant: String,
+        at: i64,
+        kind: Kind,
+        tags: Vec<u8>,
+    }
+
+    /// Encoded keys sort like the keys they came from.
+    fn assert_ordered<K>(keys: &[K])
+    where
+        K: Serialize + serde::de::DeserializeOwned + PartialOrd + std::fmt::Debug,
+    {
+        let encoded: Vec<Vec<u8>> = keys.iter().map(|key| encode(key).unwrap()).collect();
+        for (pair, bytes) in keys.windows(2).zip(encoded.windows(2)) {
+            assert!(pair[0] < pair[1], "test keys out of order: {:?}", pair);
+            assert!(
+                bytes[0] < bytes[1],
+                "{:?} encodes after {:?}",
+                pair[0],
+                pair[1]
+            );
+        }
+        for (key, bytes) in keys.iter().zip(&encoded) {
+            assert_eq!(&decode::<K>(bytes).unwrap(), key);
+        }
+    }
+
+    #[test]
+    fn test_scalars_keep_order() {
+        assert_ordered(&[i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX]);
+        assert_ordered(&[i8::MIN, -1, 0, i8::MAX]);
+        assert_ordered(&[0u32, 1, 256, u32::MAX]);
+        assert_ordered(&[f64::NEG_INFINITY, -2.5, -0.5, 0.0, 0.5, 2.5, f64::INFINITY]);
+        assert_ordered(&[false, true]);
+        assert_ordered(&['a', 'z', 'é']);
+        assert_ordered(&[
+            String::new(),
+            "\0".to_string(),
+            "\0\0".to_string(),
+            "a".to_string(),
+            "a\0".to_string(),
+            "ab".to_string(),
+            "b".to_string(),
+        ]);
+        assert_ordered(&[None, Some(0u8), Some(1)]);
+        assert_ordered(&[vec![], vec![0u16], vec![0, 0], vec![1]]);
+    }
+
+    #[test]
+    fn test_compounds_keep_order() {
+        let key = |tenant: &str, at, kind, tags: &[u8]| Key {
+            tenant: tenant.to_string(),
+            at,
+            kind,
+            tags: tags.to_vec(),
+        };
+        assert_ordered(&[
+            key("a", -5, Kind::Pair(3, None), &[]),
+            key("a", 7, Kind::Unit, &[9]),
+            key("a", 7, Kind::Named { id: 1 }, &[]),
+            key("a", 7, Kind::Pair(-1, Some("x".into())), &[]),
+            key("a", 7, Kind::Pair(-1, Some("y".into())), &[]),
+            key("ab", i64::MIN, Kind::Unit, &[]),
+        ]);
+        assert_ordered(&[("a", 2u8), ("a", 3), ("ab", 1)].map(|(s, n)| (s.to_string(), n)));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let bytes = encode(&("a", 1u32)).unwrap();
+        assert!(matches!(
+            decode::<(String, u32)>(&bytes[..bytes.len() - 1]),
+            Err(KeyEncodingError::Eof)
+        ));
+        assert!(matches!(
+            decode::<String>(&bytes),
+            Err(KeyEncodingError::TrailingBytes(4))
+        ));
+        assert!(matches!(
+            decode::<bool>(&[2]),
+            Err(KeyEncodingError::Malformed(_))
+        ));
+    }
+}
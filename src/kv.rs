@@ -13,3 +13,42 @@ impl KvPair {
         Self { key, value }
     }
 }
+
+/// What a WAL record does to its key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordKind {
+    #[default]
+    Put,
+    /// A tombstone; the record's value is empty.
+    Delete,
+    /// A merge operand, combined with the key's value by the DB's merge operator.
+    Merge,
+    /// A put that expires at the given time, in milliseconds since the Unix epoch.
+    Expiring(u64),
+    /// Bytes appended to the key's value.
+    Append,
+    /// A range tombstone deleting every key from the record's key up to,
+    /// but not including, its value.
+    DeleteRange,
+}
+
+/// Borrowed view of a `KvPair`.
+///
+/// Has the same serialized form as `KvPair`, so a record can be decoded
+/// straight out of a read buffer without allocating its key and value.
+/// The WAL stores `kind` outside that form and fills it in on replay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KvRecord<'a> {
+    #[serde(borrow)]
+    pub key: &'a [u8],
+    #[serde(borrow)]
+    pub value: &'a [u8],
+    #[serde(skip)]
+    pub kind: RecordKind,
+}
+
+impl From<KvRecord<'_>> for KvPair {
+    fn from(record: KvRecord<'_>) -> Self {
+        KvPair::new(record.key.to_vec(), record.value.to_vec())
+    }
+}
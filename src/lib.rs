@@ -1,10 +1,28 @@
 pub use crate::db::DB;
-pub use crate::kv::KvPair;
-pub use crate::skip_list::{SkipList, SkipListError};
-pub use crate::wal::Wal;
+pub use crate::kv::{KvPair, KvRecord};
+pub use crate::options::{Options, SyncPolicy};
+pub use crate::skip_list::{GenericSkipList, Iter, SkipList, SkipListError};
+pub use crate::sstable::SSTableBuilder;
+pub use crate::typed::TypedDb;
+pub use crate::wal::{RecordFraming, Wal, WalRecoveryMode};
+pub use crate::write_batch::WriteBatch;
 
+pub mod backup;
+pub mod checksum;
+#[cfg(feature = "repl")]
 pub mod client;
 pub mod db;
+pub mod hot_keys;
+pub mod key_encoding;
 pub mod kv;
+pub mod options;
+pub mod range_lock;
+pub mod rate_limit;
+pub mod schema;
 pub mod skip_list;
+pub mod sstable;
+pub mod stored_value;
+pub mod ts;
+pub mod typed;
 pub mod wal;
+pub mod write_batch;
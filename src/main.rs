@@ -1,5 +1,77 @@
-use kv_db::client;
+use kv_db::backup::BackupEngine;
+use kv_db::{client, Options, DB};
+use std::path::Path;
+use std::process;
 
 fn main() {
-    client::start();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("verify") => verify(&args[1..]),
+        Some("restore") => restore(&args[1..]),
+        // `--stdio` speaks the framed protocol for use as a subprocess
+        _ if args.iter().any(|arg| arg == "--stdio") => {
+            if let Err(e) = client::serve_stdio() {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        _ => client::start(),
+    }
+}
+
+/// `verify <wal-path>`: checks the DB logging to `wal-path` without changing
+/// its files, so it's safe against a DB another process has open. Exits 1 if
+/// anything is wrong.
+fn verify(args: &[String]) {
+    let [wal_path] = args else {
+        eprintln!("Usage: kv-db verify <wal-path>");
+        process::exit(2);
+    };
+    let db = match DB::open_secondary(Options::new(wal_path)) {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let report = db.verify();
+    if report.is_ok() {
+        println!(
+            "OK: {} WAL records, {} tables with {} entries",
+            report.wal_records, report.tables, report.table_entries
+        );
+    } else {
+        eprintln!("Verification failed: {:?}", report);
+        process::exit(1);
+    }
+}
+
+/// `restore <backup-dir> <wal-archive-dir> <new-wal-path> --until-seq <n>`:
+/// restores the DB as of sequence number `n` from its backups and WAL
+/// archive, see [`BackupEngine::restore_until`].
+fn restore(args: &[String]) {
+    let usage = || -> ! {
+        eprintln!(
+            "Usage: kv-db restore <backup-dir> <wal-archive-dir> <new-wal-path> --until-seq <n>"
+        );
+        process::exit(2);
+    };
+    let [backup_dir, archive_dir, new_path, flag, until_seq] = args else {
+        usage();
+    };
+    let until_seq = match until_seq.parse() {
+        Ok(until_seq) if flag == "--until-seq" => until_seq,
+        _ => usage(),
+    };
+    match BackupEngine::open(backup_dir).restore_until(
+        Path::new(archive_dir),
+        Path::new(new_path),
+        until_seq,
+    ) {
+        Ok(last) => println!("Restored up to sequence number {} at {}", last, new_path),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,125 @@
+use crate::wal::{RecordFraming, WalRecoveryMode};
+use std::path::{Path, PathBuf};
+
+/// When the WAL is fsynced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Leave it to the OS. Writes survive the process crashing, but a power
+    /// loss can drop the most recent ones.
+    #[default]
+    Never,
+    /// fsync after every write, so acknowledged writes survive power loss.
+    EveryWrite,
+}
+
+/// How to open a [`DB`](crate::db::DB), for [`DB::open`](crate::db::DB::open).
+///
+/// ```
+/// use kv_db::{Options, SyncPolicy};
+///
+/// # let dir = tempfile::tempdir().unwrap();
+/// let options = Options::new(dir.path().join("db.wal"))
+///     .max_level(16)
+///     .sync(SyncPolicy::EveryWrite);
+/// let db = kv_db::DB::open(options).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub(crate) wal_path: PathBuf,
+    pub(crate) data_dir: Option<PathBuf>,
+    pub(crate) memtable_size: usize,
+    pub(crate) sync: SyncPolicy,
+    pub(crate) max_level: usize,
+    pub(crate) recovery_mode: WalRecoveryMode,
+    pub(crate) framing: Option<RecordFraming>,
+    pub(crate) verify_on_open: bool,
+    pub(crate) keep_wal_segments: bool,
+}
+
+impl Options {
+    /// Options for a DB logging to `wal_path`, with defaults for everything else.
+    pub fn new(wal_path: impl Into<PathBuf>) -> Self {
+        Options {
+            wal_path: wal_path.into(),
+            data_dir: None,
+            memtable_size: 4 << 20,
+            sync: SyncPolicy::default(),
+            max_level: 12,
+            recovery_mode: WalRecoveryMode::default(),
+            framing: None,
+            verify_on_open: false,
+            keep_wal_segments: false,
+        }
+    }
+
+    /// Where the DB keeps its other files. Created on open if missing.
+    /// Defaults to the WAL's directory.
+    pub fn data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(dir.into());
+        self
+    }
+
+    /// Memtable size in bytes at which it's flushed. Defaults to 4 MiB.
+    pub fn memtable_size(mut self, bytes: usize) -> Self {
+        self.memtable_size = bytes;
+        self
+    }
+
+    /// When the WAL is fsynced. Defaults to [`SyncPolicy::Never`].
+    pub fn sync(mut self, policy: SyncPolicy) -> Self {
+        self.sync = policy;
+        self
+    }
+
+    /// Maximum skip list height of the memtable. Defaults to 12, plenty for
+    /// a few million keys.
+    pub fn max_level(mut self, max_level: usize) -> Self {
+        self.max_level = max_level;
+        self
+    }
+
+    /// How replay on open treats torn and corrupt WAL records. Defaults to
+    /// [`WalRecoveryMode::PointInTime`].
+    pub fn wal_recovery_mode(mut self, mode: WalRecoveryMode) -> Self {
+        self.recovery_mode = mode;
+        self
+    }
+
+    /// Record framing for a newly created WAL. An existing WAL keeps its
+    /// framing, and opening one written with a different framing fails.
+    pub fn record_framing(mut self, framing: RecordFraming) -> Self {
+        self.framing = Some(framing);
+        self
+    }
+
+    /// Runs [`DB::verify`](crate::db::DB::verify) once opened, failing the
+    /// open if it finds anything wrong. Off by default, since it reads every
+    /// table.
+    pub fn verify_on_open(mut self, verify: bool) -> Self {
+        self.verify_on_open = verify;
+        self
+    }
+
+    /// Keeps the WAL segment each flush closes next to the WAL, instead of
+    /// deleting it, until [`DB::archive_wal`](crate::db::DB::archive_wal)
+    /// ships it. Off by default.
+    pub fn keep_wal_segments(mut self, keep: bool) -> Self {
+        self.keep_wal_segments = keep;
+        self
+    }
+
+    pub fn wal_path(&self) -> &Path {
+        &self.wal_path
+    }
+
+    /// The data directory, falling back to the WAL's directory.
+    pub fn data_dir_path(&self) -> &Path {
+        match &self.data_dir {
+            Some(dir) => dir,
+            None => match self.wal_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            },
+        }
+    }
+}
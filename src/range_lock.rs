@@ -0,0 +1,167 @@
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+// A held range, with the thread that locked it
+type HeldRange = (Vec<u8>, Vec<u8>, ThreadId);
+
+/// Tracks which half-open key ranges `[start, end)` are currently locked.
+///
+/// Locking a range blocks until no held range overlaps it. The range is
+/// released when the returned [`RangeLockGuard`] is dropped.
+#[derive(Default)]
+pub struct RangeLocks {
+    held: Mutex<Vec<HeldRange>>,
+    released: Condvar,
+}
+
+/// Holds a locked range until dropped.
+pub struct RangeLockGuard<'a> {
+    locks: &'a RangeLocks,
+    start: Vec<u8>,
+    end: Vec<u8>,
+    owner: ThreadId,
+}
+
+fn overlaps(a_start: &[u8], a_end: &[u8], b_start: &[u8], b_end: &[u8]) -> bool {
+    a_start < b_end && b_start < a_end
+}
+
+impl RangeLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks `[start, end)`, waiting for any overlapping holders to release.
+    pub fn lock(&self, start: &[u8], end: &[u8]) -> RangeLockGuard<'_> {
+        let mut held = self.held.lock().unwrap();
+        while held.iter().any(|(s, e, _)| overlaps(s, e, start, end)) {
+            held = self.released.wait(held).unwrap();
+        }
+        self.hold(&mut held, start, end)
+    }
+
+    /// Locks `[start, end)` for a write, waiting only for overlapping ranges
+    /// held by other threads, so a thread holding a range can still write
+    /// inside it.
+    pub(crate) fn lock_for_write(&self, start: &[u8], end: &[u8]) -> RangeLockGuard<'_> {
+        let me = thread::current().id();
+        let mut held = self.held.lock().unwrap();
+        while held
+            .iter()
+            .any(|(s, e, owner)| *owner != me && overlaps(s, e, start, end))
+        {
+            held = self.released.wait(held).unwrap();
+        }
+        self.hold(&mut held, start, end)
+    }
+
+    /// Locks `[start, end)` only if nothing overlapping is currently held.
+    pub fn try_lock(&self, start: &[u8], end: &[u8]) -> Option<RangeLockGuard<'_>> {
+        let mut held = self.held.lock().unwrap();
+        if held.iter().any(|(s, e, _)| overlaps(s, e, start, end)) {
+            return None;
+        }
+        Some(self.hold(&mut held, start, end))
+    }
+
+    fn hold(&self, held: &mut Vec<HeldRange>, start: &[u8], end: &[u8]) -> RangeLockGuard<'_> {
+        let owner = thread::current().id();
+        held.push((start.to_vec(), end.to_vec(), owner));
+        RangeLockGuard {
+            locks: self,
+            start: start.to_vec(),
+            end: end.to_vec(),
+            owner,
+        }
+    }
+}
+
+impl Drop for RangeLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut held = self.locks.held.lock().unwrap();
+        if let Some(pos) = held
+            .iter()
+            .position(|(s, e, owner)| *s == self.start && *e == self.end && *owner == self.owner)
+        {
+            held.swap_remove(pos);
+        }
+        self.locks.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RangeLocks;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_disjoint_ranges_do_not_conflict() {
+        let locks = RangeLocks::new();
+        let _a = locks.lock(b"a", b"m");
+        // `end` is exclusive, so [m, z) does not overlap [a, m)
+        assert!(locks.try_lock(b"m", b"z").is_some());
+    }
+
+    #[test]
+    fn test_overlapping_range_is_refused() {
+        let locks = RangeLocks::new();
+        let guard = locks.lock(b"a", b"m");
+        assert!(locks.try_lock(b"c", b"d").is_none());
+        assert!(locks.try_lock(b"0", b"b").is_none());
+
+        drop(guard);
+        assert!(locks.try_lock(b"c", b"d").is_some());
+    }
+
+    #[test]
+    fn test_lock_waits_for_release() {
+        let locks = Arc::new(RangeLocks::new());
+        let acquired = Arc::new(AtomicBool::new(false));
+
+        let guard = locks.lock(b"a", b"z");
+
+        let handle = {
+            let locks = Arc::clone(&locks);
+            let acquired = Arc::clone(&acquired);
+            thread::spawn(move || {
+                let _g = locks.lock(b"k", b"l");
+                acquired.store(true, Ordering::SeqCst);
+            })
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!acquired.load(Ordering::SeqCst));
+
+        drop(guard);
+        handle.join().expect("thread panicked");
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_writes_wait_only_for_other_threads() {
+        let locks = Arc::new(RangeLocks::new());
+        let guard = locks.lock(b"a", b"m");
+        // The holder can write inside its own range
+        drop(locks.lock_for_write(b"c", b"d"));
+
+        let written = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let locks = Arc::clone(&locks);
+            let written = Arc::clone(&written);
+            thread::spawn(move || {
+                let _g = locks.lock_for_write(b"c", b"d");
+                written.store(true, Ordering::SeqCst);
+            })
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!written.load(Ordering::SeqCst));
+
+        drop(guard);
+        handle.join().expect("thread panicked");
+        assert!(written.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,171 @@
+use std::time::Instant;
+
+/// Token bucket refilling at `rate` tokens per second up to `capacity`.
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: u32, now: Instant) -> Self {
+        TokenBucket {
+            rate,
+            capacity: burst as f64,
+            tokens: burst as f64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Per-key-prefix write rate limits.
+///
+/// A key is governed by the longest configured prefix it starts with; keys
+/// matching no prefix are never limited.
+#[derive(Default)]
+pub struct PrefixRateLimiter {
+    buckets: Vec<(Vec<u8>, TokenBucket)>,
+}
+
+impl PrefixRateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits keys starting with `prefix` to `per_second` operations on
+    /// average, allowing bursts of up to `burst`. Replaces any existing limit.
+    pub fn set_limit(&mut self, prefix: &[u8], per_second: f64, burst: u32) {
+        self.remove_limit(prefix);
+        let bucket = TokenBucket::new(per_second, burst, Instant::now());
+        self.buckets.push((prefix.to_vec(), bucket));
+    }
+
+    pub fn remove_limit(&mut self, prefix: &[u8]) {
+        self.buckets.retain(|(p, _)| p != prefix);
+    }
+
+    /// Takes a token for `key`, returning `false` if its prefix is over the limit.
+    pub fn try_acquire(&mut self, key: &[u8]) -> bool {
+        self.try_acquire_at(key, Instant::now())
+    }
+
+    fn try_acquire_at(&mut self, key: &[u8], now: Instant) -> bool {
+        match self.bucket_for(key) {
+            Some(i) => self.buckets[i].1.try_take(now),
+            None => true,
+        }
+    }
+
+    /// Takes a token for every one of `keys`, or none at all if any of
+    /// their prefixes would go over the limit. Returns whether they were taken.
+    pub fn try_acquire_all<'a>(&mut self, keys: impl IntoIterator<Item = &'a [u8]>) -> bool {
+        self.try_acquire_all_at(keys, Instant::now())
+    }
+
+    fn try_acquire_all_at<'a>(
+        &mut self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        now: Instant,
+    ) -> bool {
+        // Tokens needed from each bucket
+        let mut needed = vec![0.0; self.buckets.len()];
+        for key in keys {
+            if let Some(i) = self.bucket_for(key) {
+                needed[i] += 1.0;
+            }
+        }
+        let enough = self
+            .buckets
+            .iter_mut()
+            .zip(&needed)
+            .all(|((_, bucket), n)| {
+                bucket.refill(now);
+                bucket.tokens >= *n
+            });
+        if enough {
+            for ((_, bucket), n) in self.buckets.iter_mut().zip(&needed) {
+                bucket.tokens -= n;
+            }
+        }
+        enough
+    }
+
+    /// Index of the bucket governing `key`: the longest prefix it starts with.
+    fn bucket_for(&self, key: &[u8]) -> Option<usize> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, (prefix, _))| key.starts_with(prefix))
+            .max_by_key(|(_, (prefix, _))| prefix.len())
+            .map(|(i, _)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrefixRateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut limiter = PrefixRateLimiter::new();
+        limiter.set_limit(b"tenant-a:", 10.0, 2);
+        let start = Instant::now();
+
+        assert!(limiter.try_acquire_at(b"tenant-a:1", start));
+        assert!(limiter.try_acquire_at(b"tenant-a:2", start));
+        assert!(!limiter.try_acquire_at(b"tenant-a:3", start));
+
+        // 10/s refills one token every 100ms
+        let later = start + Duration::from_millis(100);
+        assert!(limiter.try_acquire_at(b"tenant-a:3", later));
+        assert!(!limiter.try_acquire_at(b"tenant-a:4", later));
+    }
+
+    #[test]
+    fn test_longest_prefix_wins_and_others_are_free() {
+        let mut limiter = PrefixRateLimiter::new();
+        limiter.set_limit(b"t:", 0.0, 0);
+        limiter.set_limit(b"t:vip:", 0.0, 100);
+        let now = Instant::now();
+
+        assert!(!limiter.try_acquire_at(b"t:normal", now));
+        assert!(limiter.try_acquire_at(b"t:vip:1", now));
+        assert!(limiter.try_acquire_at(b"other", now));
+
+        limiter.remove_limit(b"t:");
+        assert!(limiter.try_acquire_at(b"t:normal", now));
+    }
+
+    #[test]
+    fn test_acquire_all_or_nothing() {
+        let mut limiter = PrefixRateLimiter::new();
+        limiter.set_limit(b"a:", 0.0, 2);
+        limiter.set_limit(b"b:", 0.0, 1);
+        let now = Instant::now();
+
+        // b: only has one token, so a: keeps both of its own
+        let keys: [&[u8]; 3] = [b"a:1", b"b:1", b"b:2"];
+        assert!(!limiter.try_acquire_all_at(keys, now));
+        let keys: [&[u8]; 3] = [b"a:1", b"a:2", b"b:1"];
+        assert!(limiter.try_acquire_all_at(keys, now));
+        assert!(!limiter.try_acquire_at(b"a:3", now));
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SchemaError {
+    #[error("Value is empty, missing schema version")]
+    MissingVersion,
+    #[error("No upgrade registered from schema version {0}")]
+    MissingUpgrade(u8),
+    #[error("Schema version {0} is newer than current version {1}")]
+    UnknownVersion(u8, u8),
+}
+
+/// Upgrades a payload from one schema version to the next.
+pub type Upgrade = Box<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// Versioned value envelope: `[1-byte schema version] [payload]`.
+///
+/// Values written at older versions are upgraded one version at a time
+/// through the registered functions when they are decoded.
+pub struct SchemaRegistry {
+    current: u8,
+    upgrades: HashMap<u8, Upgrade>,
+}
+
+impl SchemaRegistry {
+    /// Creates a registry whose newly encoded values use version `current`.
+    pub fn new(current: u8) -> Self {
+        SchemaRegistry {
+            current,
+            upgrades: HashMap::new(),
+        }
+    }
+
+    pub fn current_version(&self) -> u8 {
+        self.current
+    }
+
+    /// Registers the function that turns a `from` payload into a `from + 1` payload.
+    pub fn register_upgrade<F>(&mut self, from: u8, upgrade: F)
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.upgrades.insert(from, Box::new(upgrade));
+    }
+
+    /// Wraps `payload` in an envelope tagged with the current version.
+    pub fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut value = Vec::with_capacity(payload.len() + 1);
+        value.push(self.current);
+        value.extend_from_slice(payload);
+        value
+    }
+
+    /// Unwraps an envelope, upgrading the payload to the current version.
+    pub fn decode(&self, value: &[u8]) -> Result<Vec<u8>, SchemaError> {
+        let (&version, payload) = value.split_first().ok_or(SchemaError::MissingVersion)?;
+        if version > self.current {
+            return Err(SchemaError::UnknownVersion(version, self.current));
+        }
+
+        let mut payload = payload.to_vec();
+        for from in version..self.current {
+            let upgrade = self
+                .upgrades
+                .get(&from)
+                .ok_or(SchemaError::MissingUpgrade(from))?;
+            payload = upgrade(&payload);
+        }
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SchemaError, SchemaRegistry};
+
+    #[test]
+    fn test_encode_decode_current_version() {
+        let registry = SchemaRegistry::new(1);
+        let value = registry.encode(b"payload");
+        assert_eq!(value[0], 1);
+        assert_eq!(registry.decode(&value).unwrap(), b"payload".to_vec());
+    }
+
+    #[test]
+    fn test_upgrades_are_chained() {
+        let old = SchemaRegistry::new(0);
+        let stored = old.encode(b"v0");
+
+        let mut registry = SchemaRegistry::new(2);
+        registry.register_upgrade(0, |p| [p, b"+1"].concat());
+        registry.register_upgrade(1, |p| [p, b"+2"].concat());
+
+        assert_eq!(registry.decode(&stored).unwrap(), b"v0+1+2".to_vec());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut registry = SchemaRegistry::new(2);
+        registry.register_upgrade(1, |p| p.to_vec());
+
+        assert_eq!(registry.decode(b""), Err(SchemaError::MissingVersion));
+        assert_eq!(
+            registry.decode(&[3, 0xAA]),
+            Err(SchemaError::UnknownVersion(3, 2))
+        );
+        assert_eq!(
+            registry.decode(&[0, 0xAA]),
+            Err(SchemaError::MissingUpgrade(0))
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use log::debug;
 use rand::rngs::SmallRng;
 use rand::{Rng, SeedableRng};
+use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::fmt::Debug;
 use thiserror::Error;
@@ -12,31 +13,56 @@ pub enum SkipListError {
 }
 
 #[derive(Clone, Debug)]
-pub struct Node {
-    pub key: Option<Vec<u8>>,
-    value: Option<Vec<u8>>,
+pub struct Node<K, V> {
+    pub key: Option<K>,
+    value: Option<V>,
     pub forward: Vec<Option<usize>>,
+    // span[i] is how many level-0 steps forward[i] skips; for a `None`
+    // pointer it is the number of nodes after this one
+    span: Vec<usize>,
 }
 
-pub struct SkipList {
+/// Arena-backed skip list over any ordered key type.
+///
+/// All nodes live in one `Vec` and link to each other by index. Each forward
+/// pointer also records how many entries it skips, so [`rank`](Self::rank)
+/// and [`select`](Self::select) run in O(log n). [`SkipList`]
+/// is this list specialised to byte keys and values, as used by the DB.
+///
+/// ```
+/// use kv_db::GenericSkipList;
+///
+/// let mut scores: GenericSkipList<String, u32> = GenericSkipList::new(8);
+/// scores.put("bob".to_string(), 20).unwrap();
+/// scores.put("alice".to_string(), 10).unwrap();
+///
+/// // Lookups borrow, so a `&str` works for `String` keys
+/// assert_eq!(scores.get_ref("alice").unwrap(), &10);
+/// ```
+pub struct GenericSkipList<K, V> {
     pub head: usize, // Always points to the sentinel node
-    pub nodes: Vec<Node>,
+    pub nodes: Vec<Node<K, V>>,
     max_level: usize,
     pub current_level: usize,
 
-    // Reusable vector to avoid re-allocating on every insert
+    // Reusable vectors to avoid re-allocating on every insert
     update_buffer: Vec<Option<usize>>,
+    rank_buffer: Vec<usize>,
 
     // Keep a fast RNG as part of the struct
     rng: SmallRng,
 }
 
-impl SkipList {
+/// Skip list with raw byte keys and values.
+pub type SkipList = GenericSkipList<Vec<u8>, Vec<u8>>;
+
+impl<K: Ord, V> GenericSkipList<K, V> {
     pub fn new(max_level: usize) -> Self {
         let head_node = Node {
             key: None,
             value: None,
             forward: vec![None; max_level + 1],
+            span: vec![0; max_level + 1],
         };
 
         // Pre-allocate a decent capacity if you have a sense of how many inserts you’ll do.
@@ -44,13 +70,14 @@ impl SkipList {
         let mut nodes = Vec::with_capacity(1000);
         nodes.push(head_node);
 
-        SkipList {
+        GenericSkipList {
             head: 0,
             nodes,
             max_level,
             current_level: 0,
             // Reusable buffer (max_level + 1)
             update_buffer: vec![None; max_level + 1],
+            rank_buffer: vec![0; max_level + 1],
             // Seed can be anything; for reproducibility, you might supply your own seed
             rng: SmallRng::from_entropy(),
         }
@@ -66,19 +93,25 @@ impl SkipList {
         level
     }
 
-    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), SkipListError> {
+    pub fn put(&mut self, key: K, value: V) -> Result<(), SkipListError> {
         let level = self.random_level();
-        debug!("Inserting key {:?} with level {}", key, level);
+        debug!("Inserting key with level {}", level);
 
         // Instead of creating a new Vec on every insert, clear and reuse the buffer
         self.update_buffer.fill(None);
+        self.rank_buffer.fill(0);
 
         let mut current = self.head;
-        // Find the update path for each level (top-down)
+        let mut rank = 0;
+        // Find the update path for each level (top-down), along with the
+        // rank of each node on it
         for i in (0..=self.current_level).rev() {
             while let Some(next_idx) = self.nodes[current].forward[i] {
                 match self.nodes[next_idx].key.as_ref().unwrap().cmp(&key) {
-                    Ordering::Less => current = next_idx,
+                    Ordering::Less => {
+                        rank += self.nodes[current].span[i];
+                        current = next_idx;
+                    }
                     Ordering::Equal => {
                         // If key already exists, just update the value
                         self.nodes[next_idx].value = Some(value);
@@ -88,13 +121,15 @@ impl SkipList {
                 }
             }
             self.update_buffer[i] = Some(current);
+            self.rank_buffer[i] = rank;
         }
 
         // Create new node
         let new_node = Node {
-            key: Some(key.clone()),
+            key: Some(key),
             value: Some(value),
             forward: vec![None; level + 1],
+            span: vec![0; level + 1],
         };
 
         // We can optionally reserve additional space if we anticipate growth
@@ -104,11 +139,22 @@ impl SkipList {
         let new_index = self.nodes.len();
         self.nodes.push(new_node);
 
-        // Update forward pointers
+        // Update forward pointers, splitting each predecessor's span around
+        // the new node
+        let new_rank = self.rank_buffer[0] + 1;
         for i in 0..=level {
             let upd = self.update_buffer[i].unwrap_or(self.head);
+            let before = new_rank - self.rank_buffer[i];
             self.nodes[new_index].forward[i] = self.nodes[upd].forward[i];
+            self.nodes[new_index].span[i] = self.nodes[upd].span[i] + 1 - before;
             self.nodes[upd].forward[i] = Some(new_index);
+            self.nodes[upd].span[i] = before;
+        }
+
+        // Pointers above the new node's level now skip one more entry
+        for i in (level + 1)..=self.max_level {
+            let upd = self.update_buffer[i].unwrap_or(self.head);
+            self.nodes[upd].span[i] += 1;
         }
 
         // Update the current level if necessary
@@ -123,19 +169,290 @@ impl SkipList {
         Ok(())
     }
 
-    /// Retrieves a reference to the value associated with the given key in the skip list.
+    /// Returns the index of the node holding `key`, if there is one.
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = self.head;
+        // we start the search at the highest level, and go down
+        for level in (0..=self.current_level).rev() {
+            while let Some(next_idx) = self.nodes[current].forward[level] {
+                let node_key: &Q = self.nodes[next_idx].key.as_ref().unwrap().borrow();
+                match node_key.cmp(key) {
+                    // go to the next index
+                    Ordering::Less => current = next_idx,
+                    // we found the node
+                    Ordering::Equal => return Some(next_idx),
+                    // break out of the loop and go down a level
+                    Ordering::Greater => break,
+                }
+            }
+        }
+        None
+    }
+
+    /// Retrieves a reference to the value stored for `key`.
+    ///
+    /// `key` can be any borrowed form of the key type (e.g. `&str` for
+    /// `String` keys, `&[u8]` for `Vec<u8>` keys).
+    pub fn get_ref<Q>(&self, key: &Q) -> Result<&V, SkipListError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.find(key)
+            .and_then(|idx| self.nodes[idx].value.as_ref())
+            .ok_or(SkipListError::KeyNotFound)
+    }
+
+    /// Returns the zero-based position of `key` in sorted order, if present.
+    pub fn rank<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = self.head;
+        let mut rank = 0;
+        for level in (0..=self.current_level).rev() {
+            while let Some(next_idx) = self.nodes[current].forward[level] {
+                let node_key: &Q = self.nodes[next_idx].key.as_ref().unwrap().borrow();
+                match node_key.cmp(key) {
+                    Ordering::Less => {
+                        rank += self.nodes[current].span[level];
+                        current = next_idx;
+                    }
+                    // Ranks count from 1 along the spans, from 0 for callers
+                    Ordering::Equal => return Some(rank + self.nodes[current].span[level] - 1),
+                    Ordering::Greater => break,
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the entry at zero-based position `index` in sorted order, so
+    /// `select(0)` is the smallest key and `select(len() - 1)` the largest.
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        let target = index.checked_add(1)?;
+        let mut current = self.head;
+        let mut traversed = 0;
+        for level in (0..=self.current_level).rev() {
+            while let Some(next_idx) = self.nodes[current].forward[level] {
+                let span = self.nodes[current].span[level];
+                if traversed + span > target {
+                    break;
+                }
+                traversed += span;
+                current = next_idx;
+            }
+            if traversed == target {
+                return self.entry(current);
+            }
+        }
+        None
+    }
+
+    /// Returns the entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.nodes[self.head].forward[0].and_then(|idx| self.entry(idx))
+    }
+
+    /// Returns the entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        let mut current = self.head;
+        for level in (0..=self.current_level).rev() {
+            while let Some(next_idx) = self.nodes[current].forward[level] {
+                current = next_idx;
+            }
+        }
+        self.entry(current)
+    }
+
+    /// Returns the entry with the largest key at or before `key`.
+    pub fn get_floor<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entry(self.find_last_before(key, true))
+    }
+
+    /// Returns the entry with the largest key strictly before `key`.
+    pub fn get_lower<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entry(self.find_last_before(key, false))
+    }
+
+    /// Returns the entry with the smallest key at or after `key`.
+    pub fn get_ceiling<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let before = self.find_last_before(key, false);
+        self.nodes[before].forward[0].and_then(|idx| self.entry(idx))
+    }
+
+    /// Returns the index of the last node whose key is below `key` (or equal
+    /// to it, if `inclusive`), or the head if there is none.
+    fn find_last_before<Q>(&self, key: &Q, inclusive: bool) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut current = self.head;
+        for level in (0..=self.current_level).rev() {
+            while let Some(next_idx) = self.nodes[current].forward[level] {
+                let node_key: &Q = self.nodes[next_idx].key.as_ref().unwrap().borrow();
+                match node_key.cmp(key) {
+                    Ordering::Less => current = next_idx,
+                    Ordering::Equal if inclusive => return next_idx,
+                    _ => break,
+                }
+            }
+        }
+        current
+    }
+
+    /// Iterates over all entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            list: self,
+            next: self.nodes[self.head].forward[0],
+        }
+    }
+
+    /// Iterates in key order over the entries with keys at or after `start`.
+    pub fn iter_from<Q>(&self, start: &Q) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let before = self.find_last_before(start, false);
+        Iter {
+            list: self,
+            next: self.nodes[before].forward[0],
+        }
+    }
+
+    /// Iterates in key order over the entries with keys strictly after `start`.
+    pub fn iter_after<Q>(&self, start: &Q) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let before = self.find_last_before(start, true);
+        Iter {
+            list: self,
+            next: self.nodes[before].forward[0],
+        }
+    }
+
+    /// The key and value stored at `idx`; `None` for the head.
+    fn entry(&self, idx: usize) -> Option<(&K, &V)> {
+        let node = &self.nodes[idx];
+        Some((node.key.as_ref()?, node.value.as_ref()?))
+    }
+
+    /// Retrieves a mutable reference to the value stored for `key`.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Result<&mut V, SkipListError>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.find(key) {
+            Some(idx) => self.nodes[idx]
+                .value
+                .as_mut()
+                .ok_or(SkipListError::KeyNotFound),
+            None => Err(SkipListError::KeyNotFound),
+        }
+    }
+
+    /// Number of key/value pairs stored.
+    pub fn len(&self) -> usize {
+        // Every node apart from the sentinel holds a key
+        self.nodes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Counts adjacent nodes whose keys are not strictly increasing, summed
+    /// over every level. A healthy list always returns 0.
+    pub fn ordering_violations(&self) -> usize {
+        let mut violations = 0;
+        for level in 0..=self.current_level {
+            let mut previous: Option<&K> = None;
+            let mut current = self.head;
+            while let Some(next_idx) = self.nodes[current].forward[level] {
+                let key = self.nodes[next_idx].key.as_ref();
+                if previous >= key {
+                    violations += 1;
+                }
+                previous = key;
+                current = next_idx;
+            }
+        }
+        violations
+    }
+}
+
+/// Iterator over a skip list's entries in key order, following the
+/// level-0 links.
+pub struct Iter<'a, K, V> {
+    list: &'a GenericSkipList<K, V>,
+    next: Option<usize>,
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.next?;
+        self.next = self.list.nodes[idx].forward[0];
+        self.list.entry(idx)
+    }
+}
+
+impl<K: Debug, V: Debug> GenericSkipList<K, V> {
+    // Optional: For debug use only; remove or feature-gate to reduce overhead
+    pub fn print_debug(&self) {
+        debug!("SkipList state: current_level = {}", self.current_level);
+        debug!("Total nodes = {}", self.nodes.len());
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i == self.head {
+                debug!("Head Node {}: forward={:?}", i, node.forward);
+            } else {
+                debug!(
+                    "Node {}: key={:?}, value={:?}, forward={:?}",
+                    i, node.key, node.value, node.forward
+                );
+            }
+        }
+    }
+}
+
+impl SkipList {
+    /// Retrieves a copy of the value associated with the given key in the skip list.
     ///
     /// This function performs a search through the skip list for the specified key.
-    /// If the key exists, it returns a reference to the associated value.
+    /// If the key exists, it returns the associated value.
     /// If the key is not found, it returns a [`SkipListError::KeyNotFound`] error.
     ///
     /// # Arguments
     ///
-    /// * `key` - A reference to the key to search for in the skip list.
+    /// * `key` - The key to search for; anything that derefs to bytes (`Vec<u8>`, `&[u8]`, `b"..."`).
     ///
     /// # Returns
     ///
-    /// * `Ok(&V)` - A reference to the value associated with the key.
+    /// * `Ok(Vec<u8>)` - The value associated with the key.
     /// * `Err(SkipListError)` - An error if the key is not found.
     ///
     /// # Examples
@@ -165,46 +482,24 @@ impl SkipList {
     ///     Err(SkipListError::KeyNotFound) => println!("Key not found."),
     /// }
     /// ```
-    pub fn get(&self, key: Vec<u8>) -> Result<Vec<u8>, SkipListError> {
-        let mut current = self.head;
-        // we start the search at the highest level, and go down
-        for level in (0..=self.current_level).rev() {
-            while let Some(next_idx) = self.nodes[current].forward[level] {
-                match self.nodes[next_idx].key.clone().unwrap().cmp(&key) {
-                    // go to the next index
-                    Ordering::Less => current = next_idx,
-                    // we found the node
-                    Ordering::Equal => {
-                        return self.nodes[next_idx]
-                            .value
-                            .clone()
-                            .ok_or(SkipListError::KeyNotFound);
-                    }
-                    // break out of the loop and go down a level
-                    Ordering::Greater => break,
-                }
-            }
-        }
-        Err(SkipListError::KeyNotFound)
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Vec<u8>, SkipListError> {
+        self.get_ref(key.as_ref()).cloned()
     }
 
-    // Optional: For debug use only; remove or feature-gate to reduce overhead
-    pub fn print_debug(&self) {
-        debug!("SkipList state: current_level = {}", self.current_level);
-        debug!("Total nodes = {}", self.nodes.len());
-        for (i, node) in self.nodes.iter().enumerate() {
-            if i == self.head {
-                debug!("Head Node {}: forward={:?}", i, node.forward);
-            } else {
-                debug!(
-                    "Node {}: key={:?}, value={:?}, forward={:?}",
-                    i, node.key, node.value, node.forward
-                );
-            }
-        }
+    /// Total bytes of keys and values stored, not counting node overhead.
+    pub fn data_size(&self) -> usize {
+        self.nodes
+            .iter()
+            .map(|node| {
+                node.key.as_ref().map_or(0, Vec::len) + node.value.as_ref().map_or(0, Vec::len)
+            })
+            .sum()
     }
 }
 #[cfg(test)]
+// Most tests predate the borrowed-key `get` and keep passing owned keys, which
+// is the `Vec<u8>` caller path it must not break
+#[allow(clippy::unnecessary_to_owned)]
 mod tests {
     use super::*;
     use env_logger::{Builder, Env};
@@ -517,6 +812,20 @@ mod tests {
         assert!(list.get(b"\x2B".to_vec()).is_err());
     }
 
+    #[test]
+    fn test_get_with_borrowed_keys() {
+        init_logger();
+
+        let mut list = SkipList::new(5);
+        list.put(b"key".to_vec(), b"value".to_vec()).unwrap();
+
+        let owned = b"key".to_vec();
+        assert_eq!(list.get(&owned).unwrap(), b"value".to_vec());
+        assert_eq!(list.get(&owned[..]).unwrap(), b"value".to_vec());
+        assert_eq!(list.get(b"key").unwrap(), b"value".to_vec());
+        assert_eq!(list.get("key").unwrap(), b"value".to_vec());
+    }
+
     #[test]
     fn test_skiplist_duplicate_inserts() {
         init_logger();
@@ -532,6 +841,141 @@ mod tests {
         assert_eq!(list.get(b"\x64".to_vec()).unwrap(), b"cent".to_vec());
     }
 
+    #[test]
+    fn test_len_and_ordering_violations() {
+        init_logger();
+
+        let mut list = SkipList::new(4);
+        assert!(list.is_empty());
+        for i in (0u32..50).rev() {
+            list.put(i.to_be_bytes().to_vec(), b"v".to_vec()).unwrap();
+        }
+        // Overwrites don't add nodes
+        list.put(7u32.to_be_bytes().to_vec(), b"w".to_vec())
+            .unwrap();
+        assert_eq!(list.len(), 50);
+        assert_eq!(list.ordering_violations(), 0);
+
+        // Swap two keys on the bottom level behind the list's back
+        let first = list.nodes[list.head].forward[0].unwrap();
+        let second = list.nodes[first].forward[0].unwrap();
+        let tmp = list.nodes[first].key.take();
+        list.nodes[first].key = list.nodes[second].key.take();
+        list.nodes[second].key = tmp;
+        assert!(list.ordering_violations() > 0);
+    }
+
+    #[test]
+    fn test_generic_keys_and_values() {
+        init_logger();
+
+        let mut list: GenericSkipList<String, u64> = GenericSkipList::new(8);
+        for (i, word) in ["pear", "apple", "fig", "banana"].iter().enumerate() {
+            list.put(word.to_string(), i as u64).unwrap();
+        }
+        list.put("fig".to_string(), 99).unwrap();
+
+        assert_eq!(list.len(), 4);
+        assert_eq!(*list.get_ref("apple").unwrap(), 1);
+        assert_eq!(*list.get_ref("fig").unwrap(), 99);
+        assert!(list.get_ref("cherry").is_err());
+        assert_eq!(list.ordering_violations(), 0);
+
+        *list.get_mut("pear").unwrap() += 1;
+        assert_eq!(*list.get_ref("pear").unwrap(), 1);
+        assert!(list.get_mut("cherry").is_err());
+
+        // Values don't need to be Clone or Debug
+        struct Opaque(u8);
+        let mut opaque: GenericSkipList<i32, Opaque> = GenericSkipList::new(4);
+        opaque.put(-1, Opaque(7)).unwrap();
+        assert_eq!(opaque.get_ref(&-1).unwrap().0, 7);
+    }
+
+    #[test]
+    fn test_rank_and_select() {
+        init_logger();
+
+        let mut list: GenericSkipList<u32, u32> = GenericSkipList::new(6);
+        assert_eq!(list.select(0), None);
+
+        // Insert out of order, with some overwrites, so spans get split at
+        // every position
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut expected = std::collections::BTreeMap::new();
+        for _ in 0..2000 {
+            let key = rng.gen_range(0..500);
+            list.put(key, key * 10).unwrap();
+            expected.insert(key, key * 10);
+        }
+
+        assert_eq!(list.len(), expected.len());
+        for (i, (key, value)) in expected.iter().enumerate() {
+            assert_eq!(list.rank(key), Some(i));
+            assert_eq!(list.select(i), Some((key, value)));
+        }
+        assert_eq!(list.select(expected.len()), None);
+        assert_eq!(list.select(usize::MAX), None);
+        assert_eq!(list.rank(&1000), None);
+    }
+
+    #[test]
+    fn test_first_last_floor_ceiling() {
+        init_logger();
+
+        let mut list: GenericSkipList<u64, &str> = GenericSkipList::new(5);
+        assert_eq!(list.first(), None);
+        assert_eq!(list.last(), None);
+        assert_eq!(list.get_floor(&10), None);
+
+        for (ts, v) in [(30, "c"), (10, "a"), (20, "b")] {
+            list.put(ts, v).unwrap();
+        }
+
+        assert_eq!(list.first(), Some((&10, &"a")));
+        assert_eq!(list.last(), Some((&30, &"c")));
+
+        assert_eq!(list.get_floor(&20), Some((&20, &"b")));
+        assert_eq!(list.get_floor(&25), Some((&20, &"b")));
+        assert_eq!(list.get_floor(&5), None);
+        assert_eq!(list.get_floor(&99), Some((&30, &"c")));
+
+        assert_eq!(list.get_ceiling(&20), Some((&20, &"b")));
+        assert_eq!(list.get_ceiling(&15), Some((&20, &"b")));
+        assert_eq!(list.get_ceiling(&5), Some((&10, &"a")));
+        assert_eq!(list.get_ceiling(&31), None);
+
+        assert_eq!(list.get_lower(&20), Some((&10, &"a")));
+        assert_eq!(list.get_lower(&10), None);
+    }
+
+    #[test]
+    fn test_iter_and_iter_from() {
+        let mut list: GenericSkipList<u8, u8> = GenericSkipList::new(4);
+        for k in [5, 1, 9, 3] {
+            list.put(k, k * 2).unwrap();
+        }
+
+        let keys: Vec<u8> = list.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![1, 3, 5, 9]);
+        let from: Vec<(u8, u8)> = list.iter_from(&4).map(|(k, v)| (*k, *v)).collect();
+        assert_eq!(from, vec![(5, 10), (9, 18)]);
+        assert_eq!(list.iter_from(&10).next(), None);
+        assert_eq!(list.iter_after(&5).next(), Some((&9, &18)));
+        assert_eq!(list.iter_after(&4).next(), Some((&5, &10)));
+    }
+
+    #[test]
+    fn test_data_size() {
+        let mut list = SkipList::new(4);
+        assert_eq!(list.data_size(), 0);
+        list.put(b"ab".to_vec(), b"cde".to_vec()).unwrap();
+        list.put(b"f".to_vec(), Vec::new()).unwrap();
+        assert_eq!(list.data_size(), 6);
+        list.put(b"ab".to_vec(), b"c".to_vec()).unwrap();
+        assert_eq!(list.data_size(), 4);
+    }
+
     #[test]
     fn test_skiplist_forward_pointers_integrity() {
         init_logger();
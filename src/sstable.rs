@@ -0,0 +1,838 @@
+// --------------- sstable.rs ---------------
+use crate::checksum::crc32;
+use crate::db::{Entry, RangeTombstone};
+use log::warn;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// An SSTable is a sorted run of entries, immutable once written:
+//
+//     block*    entries, then a CRC-32 of them (u32)
+//     entry     [key len: u32][key][sequence: u64][len: u32][tag: u8][payload]
+//     index     [blocks: u32], then per block [key len: u32][last key]
+//               [offset: u64][len: u32], then [key len: u32][first key],
+//               then [range tombstones: u32] and per tombstone
+//               [start len: u32][start][end len: u32][end][sequence: u64]
+//     footer    [index offset: u64][index len: u32][index crc: u32]
+//               [entries: u64][magic]
+//
+// Integers are big-endian. `len` counts the tag and payload; block lengths
+// in the index leave out the trailing CRC. A key's versions are newest first
+// and never split across blocks. Sequence 0 stands for the sequence number
+// the whole table was given, which is how `SSTableBuilder` writes entries.
+// Range tombstones are few, so they're kept with the index rather than in
+// blocks; tables written before they existed end the index at the first key.
+
+const MAGIC: [u8; 8] = *b"kvdbsst1";
+const FOOTER_LEN: usize = 32;
+
+// Blocks are closed once they reach this many bytes
+const BLOCK_SIZE: usize = 4096;
+
+// A block's index entry: its last key, offset and length
+type BlockHandle = (Vec<u8>, u64, u32);
+
+/// A key, the sequence a version of it was written at, and that version.
+pub(crate) type Version = (Vec<u8>, u64, Entry);
+
+// Entry tags. An expiring value's payload is its expiry (u64 ms since the
+// epoch) then the value; a merge's is a has-base byte, the base if any,
+// then the operands, each length-prefixed.
+const VALUE: u8 = 0;
+const TOMBSTONE: u8 = 1;
+const EXPIRING: u8 = 2;
+const MERGE: u8 = 3;
+
+/// File listing a data directory's live tables; see [`Manifest`].
+pub(crate) const MANIFEST: &str = "MANIFEST";
+
+/// Writes an SSTable, e.g. offline for [`DB::ingest_sstable`](crate::db::DB::ingest_sstable).
+///
+/// Keys must be added in strictly increasing order. Values are stored as
+/// given, so for a DB with a schema they must already be in its envelope.
+///
+/// ```
+/// # let dir = tempfile::tempdir().unwrap();
+/// let mut builder = kv_db::SSTableBuilder::new(dir.path().join("bulk.sst")).unwrap();
+/// builder.add(b"a", b"1").unwrap();
+/// builder.add(b"b", b"2").unwrap();
+/// builder.finish().unwrap();
+/// ```
+pub struct SSTableBuilder {
+    path: PathBuf,
+    out: BufWriter<File>,
+    block: Vec<u8>,
+    // Offset the current block will be written at
+    offset: u64,
+    index: Vec<BlockHandle>,
+    first_key: Option<Vec<u8>>,
+    last_key: Option<Vec<u8>>,
+    last_sequence: u64,
+    entries: u64,
+    range_tombstones: Vec<RangeTombstone>,
+}
+
+impl SSTableBuilder {
+    /// Starts a table at `path`, which must not exist yet.
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(SSTableBuilder {
+            path,
+            out: BufWriter::new(file),
+            block: Vec::new(),
+            offset: 0,
+            index: Vec::new(),
+            first_key: None,
+            last_key: None,
+            last_sequence: 0,
+            entries: 0,
+            range_tombstones: Vec::new(),
+        })
+    }
+
+    pub fn add(&mut self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.push(key, 0, VALUE, value)
+    }
+
+    /// Adds a tombstone, hiding `key` in tables ingested before this one.
+    pub fn delete(&mut self, key: &[u8]) -> io::Result<()> {
+        self.push(key, 0, TOMBSTONE, &[])
+    }
+
+    /// Adds a range tombstone hiding the keys in `[start, end)` in tables
+    /// ingested before this one. It can be added at any point.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) -> io::Result<()> {
+        self.add_range_tombstone(start, end, 0)
+    }
+
+    /// Adds a range tombstone written at `sequence`.
+    pub(crate) fn add_range_tombstone(
+        &mut self,
+        start: &[u8],
+        end: &[u8],
+        sequence: u64,
+    ) -> io::Result<()> {
+        if start >= end {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("empty range tombstone added to {}", self.path.display()),
+            ));
+        }
+        self.range_tombstones.push(RangeTombstone {
+            start: start.to_vec(),
+            end: end.to_vec(),
+            sequence,
+        });
+        Ok(())
+    }
+
+    /// Adds a version of `key` written at `sequence`. A key's versions must
+    /// be added newest first.
+    pub(crate) fn add_entry(&mut self, key: &[u8], sequence: u64, entry: &Entry) -> io::Result<()> {
+        match entry {
+            Entry::Value(value) => self.push(key, sequence, VALUE, value),
+            Entry::Tombstone => self.push(key, sequence, TOMBSTONE, &[]),
+            Entry::Expiring { value, expires_at } => {
+                let mut payload = expires_at.to_be_bytes().to_vec();
+                payload.extend_from_slice(value);
+                self.push(key, sequence, EXPIRING, &payload)
+            }
+            Entry::Merge { base, operands } => {
+                let mut payload = vec![u8::from(base.is_some())];
+                for part in base.iter().chain(operands) {
+                    put_bytes(&mut payload, part);
+                }
+                self.push(key, sequence, MERGE, &payload)
+            }
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Writes out the index and footer and syncs the file. Returns its size.
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.finish_block()?;
+        let mut index = Vec::new();
+        index.extend_from_slice(&(self.index.len() as u32).to_be_bytes());
+        for (last_key, offset, len) in &self.index {
+            put_bytes(&mut index, last_key);
+            index.extend_from_slice(&offset.to_be_bytes());
+            index.extend_from_slice(&len.to_be_bytes());
+        }
+        put_bytes(&mut index, self.first_key.as_deref().unwrap_or_default());
+        index.extend_from_slice(&(self.range_tombstones.len() as u32).to_be_bytes());
+        for tombstone in &self.range_tombstones {
+            put_bytes(&mut index, &tombstone.start);
+            put_bytes(&mut index, &tombstone.end);
+            index.extend_from_slice(&tombstone.sequence.to_be_bytes());
+        }
+
+        let mut footer = Vec::with_capacity(FOOTER_LEN);
+        footer.extend_from_slice(&self.offset.to_be_bytes());
+        footer.extend_from_slice(&(index.len() as u32).to_be_bytes());
+        footer.extend_from_slice(&crc32(&index).to_be_bytes());
+        footer.extend_from_slice(&self.entries.to_be_bytes());
+        footer.extend_from_slice(&MAGIC);
+
+        self.out.write_all(&index)?;
+        self.out.write_all(&footer)?;
+        let file = self.out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(self.offset + (index.len() + FOOTER_LEN) as u64)
+    }
+
+    fn push(&mut self, key: &[u8], sequence: u64, tag: u8, payload: &[u8]) -> io::Result<()> {
+        let same_key = self.last_key.as_deref() == Some(key);
+        let in_order = match self.last_key.as_deref() {
+            None => true,
+            Some(_) if same_key => sequence < self.last_sequence,
+            Some(last) => key > last,
+        };
+        if !in_order {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} added to {} out of order", key, self.path.display()),
+            ));
+        }
+        // Close a full block between keys, so a key's versions share one
+        if !same_key && self.block.len() >= BLOCK_SIZE {
+            self.finish_block()?;
+        }
+        put_bytes(&mut self.block, key);
+        self.block.extend_from_slice(&sequence.to_be_bytes());
+        self.block
+            .extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+        self.block.push(tag);
+        self.block.extend_from_slice(payload);
+
+        if self.first_key.is_none() {
+            self.first_key = Some(key.to_vec());
+        }
+        if !same_key {
+            self.last_key = Some(key.to_vec());
+        }
+        self.last_sequence = sequence;
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish_block(&mut self) -> io::Result<()> {
+        let Some(last_key) = &self.last_key else {
+            return Ok(());
+        };
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        self.out.write_all(&self.block)?;
+        self.out.write_all(&crc32(&self.block).to_be_bytes())?;
+        let len = self.block.len() as u32;
+        self.index.push((last_key.clone(), self.offset, len));
+        self.offset += len as u64 + 4;
+        self.block.clear();
+        Ok(())
+    }
+}
+
+/// A table opened for reading. Only the index is held in memory; blocks are
+/// read from the file as needed and checked against their CRCs.
+pub(crate) struct SSTable {
+    path: PathBuf,
+    // What entries written with sequence 0 were written at
+    sequence: u64,
+    file: Mutex<File>,
+    index: Vec<BlockHandle>,
+    first_key: Vec<u8>,
+    range_tombstones: Vec<RangeTombstone>,
+    entries: u64,
+    size: u64,
+}
+
+impl SSTable {
+    /// Opens the table at `path`, given `sequence` if it was built by an
+    /// [`SSTableBuilder`].
+    pub(crate) fn open(path: impl Into<PathBuf>, sequence: u64) -> io::Result<Self> {
+        let path = path.into();
+        let mut file = File::open(&path)?;
+        let size = file.metadata()?.len();
+        if size < FOOTER_LEN as u64 {
+            return Err(corrupt(&path, "too short for a footer"));
+        }
+        let mut footer = [0u8; FOOTER_LEN];
+        file.seek(SeekFrom::Start(size - FOOTER_LEN as u64))?;
+        file.read_exact(&mut footer)?;
+        if footer[24..] != MAGIC {
+            return Err(corrupt(&path, "bad magic"));
+        }
+        let mut reader = Reader(&footer);
+        let index_offset = reader.u64().unwrap_or_default();
+        let index_len = reader.u32().unwrap_or_default();
+        let index_crc = reader.u32().unwrap_or_default();
+        let entries = reader.u64().unwrap_or_default();
+        if index_offset + index_len as u64 + FOOTER_LEN as u64 != size {
+            return Err(corrupt(&path, "index doesn't end at the footer"));
+        }
+
+        let mut index = vec![0u8; index_len as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut index)?;
+        if crc32(&index) != index_crc {
+            return Err(corrupt(&path, "index checksum mismatch"));
+        }
+        let (index, first_key, mut range_tombstones) =
+            parse_index(&index).ok_or_else(|| corrupt(&path, "malformed index"))?;
+        for tombstone in &mut range_tombstones {
+            if tombstone.sequence == 0 {
+                tombstone.sequence = sequence;
+            }
+        }
+
+        Ok(SSTable {
+            path,
+            sequence,
+            file: Mutex::new(file),
+            index,
+            first_key,
+            range_tombstones,
+            entries,
+            size,
+        })
+    }
+
+    /// Number of entries, tombstones and older versions included.
+    pub(crate) fn len(&self) -> u64 {
+        self.entries
+    }
+
+    /// Size of the file in bytes.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The smallest key the table holds or range-deletes, and the first key
+    /// past everything it covers. `None` for a table with no entries.
+    pub(crate) fn key_span(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let keys = self
+            .index
+            .last()
+            .map(|(last, _, _)| (self.first_key.clone(), [last.as_slice(), &[0]].concat()));
+        let tombstones = self
+            .range_tombstones
+            .iter()
+            .map(|tombstone| (tombstone.start.clone(), tombstone.end.clone()));
+        keys.into_iter()
+            .chain(tombstones)
+            .reduce(|(start, end), (s, e)| (start.min(s), end.max(e)))
+    }
+
+    /// The table's range tombstones, with the sequences they were written at.
+    pub(crate) fn range_tombstones(&self) -> &[RangeTombstone] {
+        &self.range_tombstones
+    }
+
+    /// The table's newest version of `key` written no later than `sequence`,
+    /// with the sequence it was written at.
+    pub(crate) fn get(&self, key: &[u8], sequence: u64) -> io::Result<Option<(u64, Entry)>> {
+        if key < self.first_key.as_slice() {
+            return Ok(None);
+        }
+        let block = self
+            .index
+            .partition_point(|(last, _, _)| last.as_slice() < key);
+        if block == self.index.len() {
+            return Ok(None);
+        }
+        Ok(self
+            .read_block(block)?
+            .into_iter()
+            .find(|(k, seq, _)| k == key && *seq <= sequence)
+            .map(|(_, seq, entry)| (seq, entry)))
+    }
+
+    /// Iterates over the table's entries from `start` on, in key order and
+    /// newest first for each key.
+    pub(crate) fn iter_from(&self, start: Bound<&[u8]>) -> TableIter<'_> {
+        let block = match start {
+            Bound::Included(start) | Bound::Excluded(start) => self
+                .index
+                .partition_point(|(last, _, _)| last.as_slice() < start),
+            Bound::Unbounded => 0,
+        };
+        let mut iter = TableIter {
+            table: self,
+            next_block: block,
+            entries: Vec::new().into_iter(),
+        };
+        iter.load_block();
+        // Skip to `start` within the first block
+        let rest: Vec<_> = iter
+            .entries
+            .by_ref()
+            .skip_while(|(key, _, _)| match start {
+                Bound::Included(start) => key.as_slice() < start,
+                Bound::Excluded(start) => key.as_slice() <= start,
+                Bound::Unbounded => false,
+            })
+            .collect();
+        iter.entries = rest.into_iter();
+        iter
+    }
+
+    /// The largest key within `bound` (at or below it, or strictly below for
+    /// an excluded bound), tombstones included.
+    pub(crate) fn floor(&self, bound: Bound<&[u8]>) -> io::Result<Option<Vec<u8>>> {
+        let key = match bound {
+            Bound::Included(key) | Bound::Excluded(key) => key,
+            Bound::Unbounded => return Ok(self.index.last().map(|(last, _, _)| last.clone())),
+        };
+        let block = self
+            .index
+            .partition_point(|(last, _, _)| last.as_slice() < key);
+        if block < self.index.len() {
+            let below = self
+                .read_block(block)?
+                .into_iter()
+                .rev()
+                .find(|(k, _, _)| match bound {
+                    Bound::Included(_) => k.as_slice() <= key,
+                    _ => k.as_slice() < key,
+                });
+            if let Some((k, _, _)) = below {
+                return Ok(Some(k));
+            }
+        }
+        // Every key from `block` on is past the bound
+        Ok(block
+            .checked_sub(1)
+            .map(|previous| self.index[previous].0.clone()))
+    }
+
+    /// Estimates the bytes taken up by keys in `[start, end)`, counting
+    /// whole blocks.
+    pub(crate) fn approximate_range_size(&self, start: &[u8], end: &[u8]) -> u64 {
+        let mut previous_last = None::<&[u8]>;
+        let mut size = 0;
+        for (last, _, len) in &self.index {
+            let first = previous_last.unwrap_or(&self.first_key);
+            if last.as_slice() >= start && first < end {
+                size += *len as u64 + 4;
+            }
+            previous_last = Some(last);
+        }
+        size
+    }
+
+    /// Re-reads the table from disk and checks all of it: the footer and
+    /// index checksums, every block's checksum, and that the footer's entry
+    /// count is right. Returns the entries read and how many were out of
+    /// order (by key, then newest first), within or across blocks or
+    /// outside their block's index bounds.
+    pub(crate) fn verify(&self) -> io::Result<(u64, usize)> {
+        let table = SSTable::open(&self.path, self.sequence)?;
+        let mut entries = 0;
+        let mut violations = 0;
+        let mut previous: Option<(Vec<u8>, u64)> = None;
+        for (block, (last, _, _)) in table.index.iter().enumerate() {
+            for (key, sequence, _) in table.read_block(block)? {
+                let in_order = match &previous {
+                    Some((previous, newer)) => {
+                        *previous < key || (*previous == key && *newer > sequence)
+                    }
+                    None => key >= table.first_key,
+                };
+                if !in_order || key > *last {
+                    violations += 1;
+                }
+                entries += 1;
+                previous = Some((key, sequence));
+            }
+        }
+        if entries != table.entries {
+            return Err(corrupt(
+                &self.path,
+                &format!("footer says {} entries, found {}", table.entries, entries),
+            ));
+        }
+        Ok((entries, violations))
+    }
+
+    fn read_block(&self, block: usize) -> io::Result<Vec<Version>> {
+        let (_, offset, len) = self.index[block];
+        let mut data = vec![0u8; len as usize + 4];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut data)?;
+        }
+        let (entries, crc) = data.split_at(len as usize);
+        if crc32(entries).to_be_bytes() != crc {
+            return Err(corrupt(
+                &self.path,
+                &format!("checksum mismatch in block at {}", offset),
+            ));
+        }
+        parse_block(entries, self.sequence)
+            .ok_or_else(|| corrupt(&self.path, &format!("malformed block at {}", offset)))
+    }
+}
+
+/// Iterator over a table's entries in key order, from [`SSTable::iter_from`].
+///
+/// A block that can't be read ends the iteration, with a warning.
+pub(crate) struct TableIter<'a> {
+    table: &'a SSTable,
+    next_block: usize,
+    entries: std::vec::IntoIter<Version>,
+}
+
+impl TableIter<'_> {
+    /// Moves on to the next block, returning whether there was one.
+    fn load_block(&mut self) -> bool {
+        if self.next_block >= self.table.index.len() {
+            return false;
+        }
+        match self.table.read_block(self.next_block) {
+            Ok(entries) => {
+                self.entries = entries.into_iter();
+                self.next_block += 1;
+                true
+            }
+            Err(e) => {
+                warn!("Stopping scan of {}: {}", self.table.path.display(), e);
+                self.next_block = self.table.index.len();
+                false
+            }
+        }
+    }
+}
+
+impl Iterator for TableIter<'_> {
+    type Item = Version;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(entry);
+            }
+            if !self.load_block() {
+                return None;
+            }
+        }
+    }
+}
+
+/// What a data directory's manifest records, as text lines:
+///
+/// ```text
+/// flushed <sequence> <wal bytes>
+/// table <file> <sequence> <ingest|flush>
+/// ...
+/// ```
+///
+/// Tables are listed newest first; a table's sequence is the one it was
+/// ingested at, or the newest write flushed into it. Writes up to `flushed`
+/// are in tables rather than the WAL.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Manifest {
+    pub(crate) flushed: u64,
+    /// Set while a flush resets the WAL: the WAL's length before, every byte
+    /// of which is in the tables. A WAL still this long wasn't reset yet.
+    pub(crate) wal_flushed: u64,
+    pub(crate) tables: Vec<TableMeta>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TableMeta {
+    pub(crate) file: String,
+    pub(crate) sequence: u64,
+    /// Ingested tables took a sequence number of their own, which replay
+    /// has to skip; flushed ones hold writes that were in the WAL.
+    pub(crate) ingested: bool,
+}
+
+/// Reads `dir`'s manifest. A directory without one has no tables.
+pub(crate) fn read_manifest(dir: &Path) -> io::Result<Manifest> {
+    let path = dir.join(MANIFEST);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Manifest::default()),
+        Err(e) => return Err(e),
+    };
+    let bad = |line: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("bad line in {}: {:?}", path.display(), line),
+        )
+    };
+    let mut manifest = Manifest::default();
+    for line in contents.lines() {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["flushed", sequence, wal_bytes] => {
+                manifest.flushed = sequence.parse().map_err(|_| bad(line))?;
+                manifest.wal_flushed = wal_bytes.parse().map_err(|_| bad(line))?;
+            }
+            ["table", file, sequence, kind @ ("ingest" | "flush")] => {
+                manifest.tables.push(TableMeta {
+                    file: file.to_string(),
+                    sequence: sequence.parse().map_err(|_| bad(line))?,
+                    ingested: kind == "ingest",
+                })
+            }
+            _ => return Err(bad(line)),
+        }
+    }
+    Ok(manifest)
+}
+
+/// Replaces `dir`'s manifest, via a synced temp file and a rename so it's
+/// never seen half-written.
+pub(crate) fn write_manifest(dir: &Path, manifest: &Manifest) -> io::Result<()> {
+    let path = dir.join(MANIFEST);
+    let tmp = path.with_extension("tmp");
+    {
+        let mut f = File::create(&tmp)?;
+        writeln!(f, "flushed {} {}", manifest.flushed, manifest.wal_flushed)?;
+        for table in &manifest.tables {
+            let kind = if table.ingested { "ingest" } else { "flush" };
+            writeln!(f, "table {} {} {}", table.file, table.sequence, kind)?;
+        }
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn corrupt(path: &Path, what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt SSTable {}: {}", path.display(), what),
+    )
+}
+
+// Reads big-endian fields off the front of a buffer
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
+
+fn parse_index(data: &[u8]) -> Option<(Vec<BlockHandle>, Vec<u8>, Vec<RangeTombstone>)> {
+    let mut reader = Reader(data);
+    let blocks = reader.u32()?;
+    let mut index = Vec::new();
+    for _ in 0..blocks {
+        let last_key = reader.bytes()?.to_vec();
+        index.push((last_key, reader.u64()?, reader.u32()?));
+    }
+    let first_key = reader.bytes()?.to_vec();
+    let mut range_tombstones = Vec::new();
+    if !reader.0.is_empty() {
+        for _ in 0..reader.u32()? {
+            range_tombstones.push(RangeTombstone {
+                start: reader.bytes()?.to_vec(),
+                end: reader.bytes()?.to_vec(),
+                sequence: reader.u64()?,
+            });
+        }
+    }
+    Some((index, first_key, range_tombstones))
+}
+
+fn parse_block(data: &[u8], table_sequence: u64) -> Option<Vec<Version>> {
+    let mut reader = Reader(data);
+    let mut entries = Vec::new();
+    while !reader.0.is_empty() {
+        let key = reader.bytes()?.to_vec();
+        let sequence = match reader.u64()? {
+            0 => table_sequence,
+            sequence => sequence,
+        };
+        let (&tag, payload) = reader.bytes()?.split_first()?;
+        let entry = match tag {
+            VALUE => Entry::Value(payload.to_vec()),
+            TOMBSTONE => Entry::Tombstone,
+            EXPIRING => {
+                let (expires_at, value) = payload.split_first_chunk::<8>()?;
+                Entry::Expiring {
+                    value: value.to_vec(),
+                    expires_at: u64::from_be_bytes(*expires_at),
+                }
+            }
+            MERGE => {
+                let (&has_base, parts) = payload.split_first()?;
+                let mut parts = Reader(parts);
+                let base = match has_base {
+                    0 => None,
+                    _ => Some(parts.bytes()?.to_vec()),
+                };
+                let mut operands = Vec::new();
+                while !parts.0.is_empty() {
+                    operands.push(parts.bytes()?.to_vec());
+                }
+                Entry::Merge { base, operands }
+            }
+            _ => return None,
+        };
+        entries.push((key, sequence, entry));
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SSTable, SSTableBuilder};
+    use crate::db::Entry;
+    use std::fs;
+    use std::ops::Bound;
+
+    #[test]
+    fn test_build_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.sst");
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        // Enough entries for several blocks
+        for i in 0..1000u32 {
+            let key = format!("key{:04}", i * 2);
+            builder.add(key.as_bytes(), &[b'v'; 20]).unwrap();
+        }
+        builder.delete(b"zz").unwrap();
+        assert!(builder.add(b"a", b"late").is_err());
+        builder.delete_range(b"key05", b"key06").unwrap();
+        assert!(builder.delete_range(b"b", b"a").is_err());
+        let size = builder.finish().unwrap();
+
+        let table = SSTable::open(&path, 7).unwrap();
+        assert!(table.index.len() > 1);
+        assert_eq!((table.len(), table.size()), (1001, size));
+        assert!(matches!(
+            table.get(b"key0100", 7).unwrap(),
+            Some((7, Entry::Value(v))) if v == [b'v'; 20]
+        ));
+        assert!(table.get(b"key0100", 6).unwrap().is_none());
+        assert!(matches!(
+            table.get(b"zz", 7).unwrap(),
+            Some((7, Entry::Tombstone))
+        ));
+        assert!(table.get(b"key0101", 7).unwrap().is_none());
+        assert!(table.get(b"a", 7).unwrap().is_none());
+        let tombstones = table.range_tombstones();
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!(tombstones[0].sequence, 7);
+        assert!(tombstones[0].deletes(b"key0500") && !tombstones[0].deletes(b"key0600"));
+
+        let keys: Vec<Vec<u8>> = table
+            .iter_from(Bound::Excluded(b"key1996"))
+            .map(|(key, _, _)| key)
+            .collect();
+        assert_eq!(keys, vec![b"key1998".to_vec(), b"zz".to_vec()]);
+        assert_eq!(table.iter_from(Bound::Unbounded).count(), 1001);
+
+        assert_eq!(
+            table.floor(Bound::Included(b"key0101")).unwrap(),
+            Some(b"key0100".to_vec())
+        );
+        assert_eq!(
+            table.floor(Bound::Excluded(b"key0100")).unwrap(),
+            Some(b"key0098".to_vec())
+        );
+        assert_eq!(table.floor(Bound::Excluded(b"key0000")).unwrap(), None);
+        assert_eq!(table.floor(Bound::Unbounded).unwrap(), Some(b"zz".to_vec()));
+        assert!(table.approximate_range_size(b"key", b"key0010") < size / 2);
+    }
+
+    #[test]
+    fn test_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.sst");
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        let expiring = Entry::Expiring {
+            value: b"v".to_vec(),
+            expires_at: 1234,
+        };
+        let merge = Entry::Merge {
+            base: Some(b"base".to_vec()),
+            operands: vec![b"1".to_vec(), Vec::new()],
+        };
+        builder.add_entry(b"k", 9, &merge).unwrap();
+        builder.add_entry(b"k", 4, &expiring).unwrap();
+        assert!(builder.add_entry(b"k", 4, &Entry::Tombstone).is_err());
+        // Versions of a key stay in one block however big it gets
+        for sequence in (1..=3).rev() {
+            let value = Entry::Value(vec![b'x'; 4096]);
+            builder.add_entry(b"m", sequence, &value).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let table = SSTable::open(&path, 0).unwrap();
+        assert_eq!(table.index.len(), 1);
+        assert!(matches!(
+            table.get(b"k", u64::MAX).unwrap(),
+            Some((9, Entry::Merge { base: Some(base), operands }))
+                if base == b"base" && operands.len() == 2
+        ));
+        assert!(matches!(
+            table.get(b"k", 8).unwrap(),
+            Some((4, Entry::Expiring { value, expires_at: 1234 })) if value == b"v"
+        ));
+        assert!(table.get(b"k", 3).unwrap().is_none());
+        assert!(matches!(table.get(b"m", 2).unwrap(), Some((2, _))));
+    }
+
+    #[test]
+    fn test_corruption_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("t.sst");
+        let mut builder = SSTableBuilder::new(&path).unwrap();
+        builder.add(b"k", b"value").unwrap();
+        builder.finish().unwrap();
+
+        let mut data = fs::read(&path).unwrap();
+        data[2] ^= 0xFF;
+        fs::write(&path, &data).unwrap();
+        let table = SSTable::open(&path, 1).unwrap();
+        assert!(table.get(b"k", 1).is_err());
+
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+        assert!(SSTable::open(&path, 1).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StoredValueError {
+    #[error("Value is empty, missing version byte")]
+    MissingVersion,
+    #[error("Stored value has version {found}, expected {expected}")]
+    VersionMismatch { found: u8, expected: u8 },
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+/// Encodes a type as a DB value: `[1-byte version] [bincode payload]`.
+///
+/// Implement it with an empty `impl StoredValue for MyStruct {}` (overriding
+/// `VERSION` when the struct changes shape) and store it with
+/// [`DB::put_value`](crate::db::DB::put_value) / [`DB::get_value`](crate::db::DB::get_value).
+pub trait StoredValue: Serialize + DeserializeOwned {
+    const VERSION: u8 = 0;
+
+    fn encode(&self) -> Result<Vec<u8>, StoredValueError> {
+        let mut bytes = vec![Self::VERSION];
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StoredValueError> {
+        let (&found, payload) = bytes
+            .split_first()
+            .ok_or(StoredValueError::MissingVersion)?;
+        if found != Self::VERSION {
+            return Err(StoredValueError::VersionMismatch {
+                found,
+                expected: Self::VERSION,
+            });
+        }
+        Ok(bincode::deserialize(payload)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StoredValue, StoredValueError};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    impl StoredValue for User {}
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct UserV2 {
+        name: String,
+        age: u32,
+        email: String,
+    }
+
+    impl StoredValue for UserV2 {
+        const VERSION: u8 = 2;
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let user = User {
+            name: "ada".to_string(),
+            age: 36,
+        };
+        let bytes = user.encode().unwrap();
+        assert_eq!(bytes[0], 0);
+        assert_eq!(User::decode(&bytes).unwrap(), user);
+    }
+
+    #[test]
+    fn test_version_is_checked() {
+        let bytes = User {
+            name: "ada".to_string(),
+            age: 36,
+        }
+        .encode()
+        .unwrap();
+
+        assert!(matches!(
+            UserV2::decode(&bytes),
+            Err(StoredValueError::VersionMismatch {
+                found: 0,
+                expected: 2
+            })
+        ));
+        assert!(matches!(
+            User::decode(&[]),
+            Err(StoredValueError::MissingVersion)
+        ));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::db::{DatabaseError, DB};
+use crate::stored_value::StoredValue;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+// Values are stored as a versioned f64 so a series can't be misread as
+// some other encoding
+#[derive(Serialize, Deserialize)]
+struct Sample(f64);
+
+impl StoredValue for Sample {}
+
+/// Encodes a sample key as `[u16 series length][series][u64 timestamp]`,
+/// all big-endian, so a series' samples sort together and by time.
+///
+/// The length prefix keeps series apart: `cpu` never matches `cpu2` keys.
+pub fn encode_key(series: &str, timestamp: u64) -> Vec<u8> {
+    let mut key = series_prefix(series);
+    key.extend_from_slice(&timestamp.to_be_bytes());
+    key
+}
+
+/// Splits a key made by [`encode_key`] back into its series and timestamp.
+pub fn decode_key(key: &[u8]) -> Option<(&str, u64)> {
+    let (len, rest) = key.split_first_chunk::<2>()?;
+    let len = u16::from_be_bytes(*len) as usize;
+    if rest.len() != len + 8 {
+        return None;
+    }
+    let (series, timestamp) = rest.split_at(len);
+    let series = std::str::from_utf8(series).ok()?;
+    Some((series, u64::from_be_bytes(timestamp.try_into().ok()?)))
+}
+
+fn series_prefix(series: &str) -> Vec<u8> {
+    let len = u16::try_from(series.len()).expect("series name longer than u16::MAX bytes");
+    let mut prefix = Vec::with_capacity(2 + series.len() + 8);
+    prefix.extend_from_slice(&len.to_be_bytes());
+    prefix.extend_from_slice(series.as_bytes());
+    prefix
+}
+
+/// Records `value` for `series` at `timestamp`, replacing any sample
+/// already stored at that exact timestamp.
+pub fn append(db: &DB, series: &str, timestamp: u64, value: f64) -> Result<(), DatabaseError> {
+    db.put_value(encode_key(series, timestamp), &Sample(value))
+}
+
+/// Returns the samples of `series` with timestamps in `range`, oldest first.
+///
+/// With `downsample: Some(width)`, samples are grouped into buckets of
+/// `width` time units (aligned to multiples of `width`) and each bucket is
+/// reported once, at its start time, with the mean of its samples.
+pub fn query(
+    db: &DB,
+    series: &str,
+    range: Range<u64>,
+    downsample: Option<u64>,
+) -> Result<Vec<(u64, f64)>, DatabaseError> {
+    let mut samples = Vec::new();
+    let keys = encode_key(series, range.start)..encode_key(series, range.end);
+    for (key, value) in db.range(keys) {
+        if let Some((_, timestamp)) = decode_key(&key) {
+            samples.push((timestamp, Sample::decode(&value)?.0));
+        }
+    }
+
+    match downsample {
+        Some(width) if width > 0 => Ok(downsample_mean(&samples, width)),
+        _ => Ok(samples),
+    }
+}
+
+fn downsample_mean(samples: &[(u64, f64)], width: u64) -> Vec<(u64, f64)> {
+    let mut buckets: Vec<(u64, f64, u32)> = Vec::new();
+    for &(timestamp, value) in samples {
+        let start = timestamp - timestamp % width;
+        match buckets.last_mut() {
+            Some((bucket, sum, count)) if *bucket == start => {
+                *sum += value;
+                *count += 1;
+            }
+            _ => buckets.push((start, value, 1)),
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(start, sum, count)| (start, sum / count as f64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{append, decode_key, encode_key, query};
+    use crate::db::DB;
+
+    #[test]
+    fn test_key_round_trip() {
+        let key = encode_key("cpu", 42);
+        assert_eq!(decode_key(&key), Some(("cpu", 42)));
+        assert!(encode_key("cpu", 1) < encode_key("cpu", 256));
+        assert_eq!(decode_key(b"junk"), None);
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DB::new(dir.path().join("db.wal").to_str().unwrap(), 5);
+
+        for t in 0..10 {
+            append(&db, "cpu", t * 10, t as f64).unwrap();
+            append(&db, "cpu2", t * 10, -1.0).unwrap();
+        }
+        append(&db, "a", 15, -1.0).unwrap();
+
+        let raw = query(&db, "cpu", 20..50, None).unwrap();
+        assert_eq!(raw, vec![(20, 2.0), (30, 3.0), (40, 4.0)]);
+
+        // Buckets of 30: [0, 30) holds 0, 1, 2 and [30, 60) holds 3, 4, 5
+        let downsampled = query(&db, "cpu", 0..60, Some(30)).unwrap();
+        assert_eq!(downsampled, vec![(0, 1.0), (30, 4.0)]);
+
+        assert!(query(&db, "cpu", 200..300, None).unwrap().is_empty());
+        assert!(query(&db, "missing", 0..u64::MAX, None).unwrap().is_empty());
+        assert_eq!(query(&db, "cpu2", 0..u64::MAX, None).unwrap().len(), 10);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::db::{DatabaseError, DbIter, DB};
+use crate::key_encoding::{self, KeyEncodingError};
+use crate::options::Options;
+use crate::stored_value::{StoredValue, StoredValueError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TypedDbError {
+    #[error("Database error: {0}")]
+    Db(#[from] DatabaseError),
+    #[error("Key encoding error: {0}")]
+    Key(#[from] KeyEncodingError),
+    #[error("Value encoding error: {0}")]
+    Value(#[from] StoredValueError),
+}
+
+/// A [`DB`] storing `K` keys and `V` values, so callers don't hand-roll the
+/// byte encodings.
+///
+/// Keys go through [`key_encoding`](crate::key_encoding), so the DB's key
+/// order is `K`'s order and ranges and iteration work on typed keys. Values
+/// are [`StoredValue`]s, so they carry its version byte and read back the
+/// same as with [`DB::get_value`].
+///
+/// ```
+/// # let dir = tempfile::tempdir().unwrap();
+/// use kv_db::stored_value::StoredValue;
+/// use kv_db::TypedDb;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct Reading(f64);
+/// impl StoredValue for Reading {}
+///
+/// let db = kv_db::DB::new(dir.path().join("db.wal").to_str().unwrap(), 5);
+/// let readings: TypedDb<(String, i64), Reading> = TypedDb::new(db);
+/// readings.put(&("sensor-1".to_string(), -10), &Reading(0.5)).unwrap();
+/// readings.put(&("sensor-1".to_string(), 20), &Reading(1.5)).unwrap();
+///
+/// assert_eq!(
+///     readings.get(&("sensor-1".to_string(), 20)).unwrap(),
+///     Some(Reading(1.5))
+/// );
+/// let first = readings.iter().next().unwrap().unwrap();
+/// assert_eq!(first, (("sensor-1".to_string(), -10), Reading(0.5)));
+/// ```
+pub struct TypedDb<K, V> {
+    db: DB,
+    types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: Serialize, V: StoredValue> TypedDb<K, V> {
+    /// Wraps `db`, whose keys and values must all have been written as `K`
+    /// and `V` by a `TypedDb`.
+    pub fn new(db: DB) -> Self {
+        TypedDb {
+            db,
+            types: PhantomData,
+        }
+    }
+
+    /// Opens the DB described by `options`, see [`DB::open`].
+    pub fn open(options: Options) -> Result<Self, TypedDbError> {
+        Ok(Self::new(DB::open(options)?))
+    }
+
+    /// The underlying DB, e.g. for snapshots or flushing.
+    pub fn db(&self) -> &DB {
+        &self.db
+    }
+
+    pub fn db_mut(&mut self) -> &mut DB {
+        &mut self.db
+    }
+
+    pub fn into_inner(self) -> DB {
+        self.db
+    }
+
+    pub fn put(&self, key: &K, value: &V) -> Result<(), TypedDbError> {
+        let key = key_encoding::encode(key)?;
+        let value = value.encode()?;
+        Ok(self.db.put(key, value)?)
+    }
+
+    /// Reads `key`'s value, or `None` if it has none.
+    pub fn get(&self, key: &K) -> Result<Option<V>, TypedDbError> {
+        match self.db.get(key_encoding::encode(key)?) {
+            Ok(bytes) => Ok(Some(V::decode(&bytes)?)),
+            Err(DatabaseError::KeyNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> Result<bool, TypedDbError> {
+        Ok(self.db.contains_key(key_encoding::encode(key)?))
+    }
+
+    pub fn delete(&self, key: &K) -> Result<(), TypedDbError> {
+        Ok(self.db.delete(key_encoding::encode(key)?)?)
+    }
+
+    /// Deletes the keys in `[start, end)`, see [`DB::delete_range`].
+    pub fn delete_range(&self, start: &K, end: &K) -> Result<(), TypedDbError> {
+        let start = key_encoding::encode(start)?;
+        let end = key_encoding::encode(end)?;
+        Ok(self.db.delete_range(start, end)?)
+    }
+}
+
+impl<K: Serialize + DeserializeOwned, V: StoredValue> TypedDb<K, V> {
+    /// Iterates over every entry in key order.
+    pub fn iter(&self) -> TypedIter<'_, K, V> {
+        TypedIter {
+            inner: self.db.iter(),
+            types: PhantomData,
+        }
+    }
+
+    /// Iterates in key order over the entries whose keys fall within `range`.
+    pub fn range(&self, range: impl RangeBounds<K>) -> Result<TypedIter<'_, K, V>, TypedDbError> {
+        let bound = |bound: Bound<&K>| -> Result<Bound<Vec<u8>>, KeyEncodingError> {
+            Ok(match bound {
+                Bound::Included(key) => Bound::Included(key_encoding::encode(key)?),
+                Bound::Excluded(key) => Bound::Excluded(key_encoding::encode(key)?),
+                Bound::Unbounded => Bound::Unbounded,
+            })
+        };
+        let start = bound(range.start_bound())?;
+        let end = bound(range.end_bound())?;
+        Ok(TypedIter {
+            inner: self.db.range((start, end)),
+            types: PhantomData,
+        })
+    }
+}
+
+/// Iterator over a [`TypedDb`]'s entries in key order, decoding each one.
+pub struct TypedIter<'a, K, V> {
+    inner: DbIter<'a>,
+    types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: DeserializeOwned, V: StoredValue> Iterator for TypedIter<'_, K, V> {
+    type Item = Result<(K, V), TypedDbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.inner.next()?;
+        let decoded = key_encoding::decode(&key)
+            .map_err(TypedDbError::from)
+            .and_then(|key| Ok((key, V::decode(&value)?)));
+        Some(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TypedDb, TypedDbError};
+    use crate::options::Options;
+    use crate::stored_value::{StoredValue, StoredValueError};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        item: String,
+        quantity: u32,
+    }
+
+    impl StoredValue for Order {}
+
+    #[test]
+    fn test_typed_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = Options::new(dir.path().join("db.wal"));
+        let db: TypedDb<(u32, i64), Order> = TypedDb::open(options.clone()).unwrap();
+        let order = |quantity| Order {
+            item: "widget".to_string(),
+            quantity,
+        };
+        for (customer, at) in [(2, -1), (1, 5), (1, -7), (10, 0)] {
+            db.put(&(customer, at), &order(at.unsigned_abs() as u32))
+                .unwrap();
+        }
+        db.delete(&(10, 0)).unwrap();
+        assert_eq!(db.get(&(1, 5)).unwrap(), Some(order(5)));
+        assert_eq!(db.get(&(10, 0)).unwrap(), None);
+        assert!(db.contains_key(&(2, -1)).unwrap());
+
+        // Iteration follows the key type's order, negative numbers included
+        let keys: Vec<(u32, i64)> = db.iter().map(|entry| entry.unwrap().0).collect();
+        assert_eq!(keys, vec![(1, -7), (1, 5), (2, -1)]);
+        let keys: Vec<(u32, i64)> = db
+            .range((1, 0)..(2, i64::MAX))
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(keys, vec![(1, 5), (2, -1)]);
+
+        db.delete_range(&(1, i64::MIN), &(2, -1)).unwrap();
+        drop(db);
+        let db: TypedDb<(u32, i64), Order> = TypedDb::open(options).unwrap();
+        let entries: Vec<_> = db.iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![((2, -1), order(1))]);
+    }
+
+    #[test]
+    fn test_values_share_stored_value_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let db: TypedDb<u32, Order> =
+            TypedDb::open(Options::new(dir.path().join("db.wal"))).unwrap();
+        let order = Order {
+            item: "widget".to_string(),
+            quantity: 3,
+        };
+        db.put(&1, &order).unwrap();
+
+        // A plain DB reader sees the versioned encoding
+        let key = crate::key_encoding::encode(&1u32).unwrap();
+        assert_eq!(db.db().get_value::<Order>(&key).unwrap(), order);
+
+        // and a value without the version byte is rejected rather than misread
+        db.db().put(key, b"".to_vec()).unwrap();
+        assert!(matches!(
+            db.get(&1),
+            Err(TypedDbError::Value(StoredValueError::MissingVersion))
+        ));
+    }
+}
@@ -1,122 +1,1007 @@
 // --------------- wal.rs ---------------
-use crate::kv::KvPair;
-use bincode::{deserialize, serialize};
-use std::fs::{File, OpenOptions};
-use std::io::{self, BufReader, Read, Write};
+use crate::checksum::{crc32, crc32_update};
+use crate::kv::{KvPair, KvRecord, RecordKind};
+use bincode::{deserialize, serialize, serialized_size};
+use log::warn;
+use std::borrow::Cow;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// How the length of each record is written in front of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordFraming {
+    /// 4-byte big-endian length. The original format; the file has no header.
+    #[default]
+    Fixed32,
+    /// LEB128 varint length, a single byte for records under 128 bytes.
+    /// Logs using it start with a small header so they're recognised on open.
+    Varint,
+}
+
+impl RecordFraming {
+    fn id(self) -> u8 {
+        match self {
+            RecordFraming::Fixed32 => 0,
+            RecordFraming::Varint => 1,
+        }
+    }
+
+    fn from_id(id: u8) -> io::Result<Self> {
+        match id {
+            0 => Ok(RecordFraming::Fixed32),
+            1 => Ok(RecordFraming::Varint),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown WAL record framing {}", id),
+            )),
+        }
+    }
+}
+
+/// How replay treats torn and corrupt records.
+///
+/// A torn record is one cut short by the end of the log, as a crash during
+/// an append leaves it; a corrupt record is complete but doesn't decode.
+/// Records carry no checksums yet, so a damaged length prefix usually
+/// shows up as a torn record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WalRecoveryMode {
+    /// Ignore a torn record at the end of the log; fail on corrupt records.
+    TolerateCorruptedTail,
+    /// Fail on any torn or corrupt record.
+    AbsoluteConsistency,
+    /// Stop at the first torn or corrupt record, keeping everything before
+    /// it, so the result is always a consistent prefix of the writes.
+    #[default]
+    PointInTime,
+    /// Skip corrupt records and carry on with the next one; a torn record
+    /// ends replay.
+    SkipAnyCorrupted,
+}
+
+// Header for logs that aren't plain `Fixed32`: magic bytes then the framing id.
+// As a `Fixed32` length the magic would be a ~4GB record, so it can't clash.
+const HEADER_MAGIC: [u8; 4] = [0xFF, b'K', b'V', b'W'];
+const HEADER_LEN: usize = HEADER_MAGIC.len() + 1;
+
+// A tombstone is a record with an empty value followed by this byte. bincode
+// ignores trailing bytes, so the record still decodes as a `KvPair`.
+const DELETE_MARKER: u8 = 1;
+
+// A batch is a record with an empty key whose value holds the batch's
+// operations, followed by this byte. It's the last byte of the frame, so a
+// batch whose marker made it to disk was written in full.
+const BATCH_MARKER: u8 = 2;
+
+// A merge operand is a record followed by this byte, like a tombstone.
+const MERGE_MARKER: u8 = 3;
+
+// A put with a TTL is a record followed by this byte and its expiry time, as
+// a big-endian u64 of milliseconds since the Unix epoch. Inside a batch the
+// expiry time goes in front of the value instead.
+const EXPIRE_MARKER: u8 = 4;
+
+// Bytes appended to a key's value are a record followed by this byte.
+const APPEND_MARKER: u8 = 5;
+
+// A range tombstone is a record of the range's start and end followed by
+// this byte.
+const DELETE_RANGE_MARKER: u8 = 6;
+
+// One operation in a batch record: kind id, key and value.
+type BatchOp<'a> = (u8, &'a [u8], Cow<'a, [u8]>);
 
 /// Write-Ahead Log
 ///
 /// Persists key/value pairs in a length-prefixed bincode format:
-/// [4-byte big-endian length] [bincode-serialized KvPair].
+/// [length] [bincode-serialized KvPair], where the length is encoded as
+/// described by the log's [`RecordFraming`].
 pub struct Wal {
     location: String,
     file: File,
+    offset: u64,
+    framing: RecordFraming,
+    sync_writes: bool,
+    // Sequence number the first record follows, naming the segment the log
+    // becomes when rotated
+    base: u64,
+}
+
+/// Path of the closed segment the log at `location` becomes when rotated,
+/// holding the records after sequence number `base`.
+pub fn segment_path(location: &Path, base: u64) -> PathBuf {
+    let mut path = location.as_os_str().to_os_string();
+    path.push(format!(".{:020}", base));
+    PathBuf::from(path)
+}
+
+/// The closed segments of the log at `location` (see [`Wal::rotate`]) as
+/// `(base, path)`, oldest first.
+pub fn segments(location: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let name = location.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "WAL location has no file name")
+    })?;
+    let dir = match location.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    segments_in(dir, Some(&name.to_string_lossy()))
+}
+
+/// The WAL copies [`DB::archive_wal`](crate::db::DB::archive_wal) left in
+/// `archive_dir`, as `(base, path)` oldest first.
+pub fn archived_segments(archive_dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    segments_in(archive_dir, None)
+}
+
+/// The files in `dir` named `<name>.<base>` as [`segment_path`] names them,
+/// or with any `<name>` if that's `None`, oldest first.
+fn segments_in(dir: &Path, name: Option<&str>) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some((prefix, suffix)) = file_name.to_str().and_then(|name| name.rsplit_once('.'))
+        else {
+            continue;
+        };
+        if name.is_some_and(|name| name != prefix) {
+            continue;
+        }
+        if suffix.len() == 20 && suffix.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(base) = suffix.parse() {
+                segments.push((base, entry.path()));
+            }
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Point-in-time restore across rotated logs: writes the writes up to
+/// sequence number `until_seq` from `sources`, `(base, path)` pairs oldest
+/// first as [`segments`] lists them, into a new WAL at `dest` with the first
+/// source's framing.
+///
+/// Records are numbered the way replay numbers them: from the first
+/// source's base, one sequence number per operation (so a batch takes one
+/// per write in it), skipping the sequence numbers in `ingested` that
+/// ingested tables took without logging anything. A record whose last write
+/// is past `until_seq` isn't restored, so batches stay whole. Restoring
+/// stops early at a torn record, or at a source whose base doesn't follow
+/// on from the one before.
+///
+/// Returns the sequence number of the last write restored.
+pub fn restore_segments_until(
+    sources: &[(u64, PathBuf)],
+    dest: &Path,
+    ingested: &[u64],
+    until_seq: u64,
+) -> io::Result<u64> {
+    let Some((mut sequence, first)) = sources.first().cloned() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nothing to restore from",
+        ));
+    };
+    // Don't let a mistyped source be created as an empty log
+    let first = Wal::open_read_only(first.to_string_lossy().to_string(), None)?;
+    if dest.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "restore destination already exists",
+        ));
+    }
+    let next_logged = |mut sequence: u64| {
+        sequence += 1;
+        while ingested.contains(&sequence) {
+            sequence += 1;
+        }
+        sequence
+    };
+
+    let mut restored = Wal::with_framing(dest.to_string_lossy().to_string(), first.framing)?;
+    'sources: for (base, path) in sources {
+        if *base < sequence || next_logged(*base) != next_logged(sequence) {
+            break;
+        }
+        sequence = *base;
+        let source = Wal::open_read_only(path.to_string_lossy().to_string(), None)?;
+        let mut frames = Vec::new();
+        match source.for_each_frame(|data| {
+            frames.push(data.to_vec());
+            Ok(())
+        }) {
+            Ok(()) => {}
+            // A torn tail ends the source, as it does replay
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(e) => return Err(e),
+        }
+        for frame in frames {
+            let ops = decode_frame(&frame, &mut |_| {})
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let last = (0..ops).fold(sequence, |sequence, _| next_logged(sequence));
+            if last > until_seq {
+                break 'sources;
+            }
+            restored.write_record(&frame)?;
+            sequence = last;
+        }
+    }
+    restored.file.sync_all()?;
+    Ok(sequence)
+}
+
+/// Finishes archiving a closed segment (see [`Wal::rotate`]) into
+/// `archive_dir`: ships whatever of it [`Wal::archive`] hadn't copied before
+/// the log was rotated, under the same name. Returns the segment's length.
+pub fn archive_segment(segment: &Path, archive_dir: &Path) -> io::Result<u64> {
+    let name = segment.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "WAL segment has no file name")
+    })?;
+    let len = fs::metadata(segment)?.len();
+    archive_file(segment, len, &archive_dir.join(name))
 }
 
 impl Wal {
     /// Creates a new `Wal` instance, creating the file if it doesn't exist.
     /// Opens the file for reading and appending.
+    ///
+    /// Existing logs keep the framing they were written with; new ones use
+    /// [`RecordFraming::Fixed32`].
     pub fn new(location: String) -> io::Result<Self> {
-        let file = OpenOptions::new()
+        Self::open(location, None)
+    }
+
+    /// Like [`Wal::new`], but a newly created log uses `framing`. Opening an
+    /// existing log written with a different framing is an error.
+    pub fn with_framing(location: String, framing: RecordFraming) -> io::Result<Self> {
+        Self::open(location, Some(framing))
+    }
+
+    /// Opens an existing log for reading only, e.g. one another process is
+    /// appending to. Appends fail. An empty log is taken to use `framing`,
+    /// or [`RecordFraming::Fixed32`] if that's `None`.
+    pub fn open_read_only(location: String, framing: Option<RecordFraming>) -> io::Result<Self> {
+        let mut file = File::open(&location)?;
+        let offset = file.metadata()?.len();
+        let framing = if offset > 0 {
+            detect_framing(&mut file)?
+        } else {
+            framing.unwrap_or_default()
+        };
+        Ok(Wal {
+            location,
+            file,
+            offset,
+            framing,
+            sync_writes: false,
+            base: 0,
+        })
+    }
+
+    fn open(location: String, framing: Option<RecordFraming>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
             .read(true)
             .append(true)
             .create(true)
             .open(&location)?;
+        let mut offset = file.metadata()?.len();
+
+        let existing = if offset > 0 {
+            Some(detect_framing(&mut file)?)
+        } else {
+            None
+        };
+        let framing = match (existing, framing) {
+            (Some(found), Some(wanted)) if found != wanted => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "WAL was written with a different record framing",
+                ));
+            }
+            (Some(found), _) => found,
+            (None, wanted) => {
+                let framing = wanted.unwrap_or_default();
+                if framing != RecordFraming::Fixed32 {
+                    file.write_all(&HEADER_MAGIC)?;
+                    file.write_all(&[framing.id()])?;
+                    file.flush()?;
+                    offset = HEADER_LEN as u64;
+                }
+                framing
+            }
+        };
+
+        Ok(Wal {
+            location,
+            file,
+            offset,
+            framing,
+            sync_writes: false,
+            base: 0,
+        })
+    }
+
+    /// fsyncs the log after every append from now on when `sync` is true.
+    /// Otherwise appends are only flushed to the OS.
+    pub fn set_sync_writes(&mut self, sync: bool) {
+        self.sync_writes = sync;
+    }
+
+    /// fsyncs everything appended so far.
+    pub fn sync(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// The sequence number the log's first record follows: 0 for a new log,
+    /// then whatever it was last rotated to.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Sets [`Wal::base`], e.g. when reopening a log that was rotated before.
+    pub fn set_base(&mut self, base: u64) {
+        self.base = base;
+    }
+
+    /// Closes the log as a segment once everything in it has been written
+    /// out elsewhere (i.e. flushed to an SSTable): syncs it, renames it to
+    /// its [`segment_path`] and starts a fresh log with the same framing in
+    /// its place, whose records follow `next_base`. Returns the segment's
+    /// path.
+    ///
+    /// The log is left as it was if the fresh one can't be created.
+    pub fn rotate(&mut self, next_base: u64) -> io::Result<PathBuf> {
+        self.file.sync_all()?;
+        let segment = segment_path(Path::new(&self.location), self.base);
+        fs::rename(&self.location, &segment)?;
+        let fresh = Wal::open(self.location.clone(), Some(self.framing)).and_then(|fresh| {
+            fresh.file.sync_all()?;
+            Ok(fresh)
+        });
+        match fresh {
+            Ok(fresh) => {
+                *self = Wal {
+                    sync_writes: self.sync_writes,
+                    base: next_base,
+                    ..fresh
+                };
+                Ok(segment)
+            }
+            Err(e) => {
+                let _ = fs::rename(&segment, &self.location);
+                Err(e)
+            }
+        }
+    }
 
-        Ok(Wal { location, file })
+    /// Undoes [`Wal::rotate`] while nothing has been appended since, putting
+    /// `segment` back as the log.
+    pub fn undo_rotate(&mut self, segment: &Path, base: u64) -> io::Result<()> {
+        fs::rename(segment, &self.location)?;
+        let reopened = Wal::open(self.location.clone(), Some(self.framing))?;
+        *self = Wal {
+            sync_writes: self.sync_writes,
+            base,
+            ..reopened
+        };
+        Ok(())
+    }
+
+    /// Empties the log, keeping its header, once everything in it has been
+    /// written out elsewhere (i.e. flushed to an SSTable).
+    ///
+    /// Archives made with [`Wal::archive`] are then ahead of the log, so
+    /// archiving it again fails; [`Wal::rotate`] avoids that.
+    pub fn reset(&mut self) -> io::Result<()> {
+        let header_len = match self.framing {
+            RecordFraming::Fixed32 => 0,
+            _ => HEADER_LEN as u64,
+        };
+        // The file is in append mode, so new records land after the header
+        self.file.set_len(header_len)?;
+        self.file.sync_all()?;
+        self.offset = header_len;
+        Ok(())
+    }
+
+    /// Returns the framing records in this log are written with.
+    pub fn framing(&self) -> RecordFraming {
+        self.framing
+    }
+
+    /// Returns the byte offset at which the next record will be written,
+    /// i.e. the current length of the log.
+    pub fn current_offset(&self) -> u64 {
+        self.offset
     }
 
     /// Appends a single key-value record (as raw bytes) to the WAL.
     ///
     /// 1. We bincode-serialize the `KvPair` (which already has `Vec<u8>` key + `Vec<u8>` value).
-    /// 2. We write a 4-byte length (big-endian).
+    /// 2. We write its length, framed as per `RecordFraming`.
     /// 3. We write the bytes themselves.
     /// 4. We flush to ensure durability.
     pub fn append(&mut self, kv: KvPair) -> io::Result<()> {
-        let serialized = serialize(&kv).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let serialized = serialize(&kv).map_err(io::Error::other)?;
+        self.write_record(&serialized)
+    }
 
+    /// Appends a tombstone recording that `key` was deleted.
+    pub fn append_delete(&mut self, key: &[u8]) -> io::Result<()> {
+        self.append_marked(key, &[], &[DELETE_MARKER])
+    }
+
+    /// Appends a range tombstone deleting the keys in `[start, end)`.
+    pub fn append_delete_range(&mut self, start: &[u8], end: &[u8]) -> io::Result<()> {
+        self.append_marked(start, end, &[DELETE_RANGE_MARKER])
+    }
+
+    /// Appends a merge operand for `key`.
+    pub fn append_merge(&mut self, key: &[u8], operand: &[u8]) -> io::Result<()> {
+        self.append_marked(key, operand, &[MERGE_MARKER])
+    }
+
+    /// Appends a record of `suffix` being added to the end of `key`'s value.
+    pub fn append_suffix(&mut self, key: &[u8], suffix: &[u8]) -> io::Result<()> {
+        self.append_marked(key, suffix, &[APPEND_MARKER])
+    }
+
+    /// Appends a put of `key` that expires at `expires_at`, in milliseconds
+    /// since the Unix epoch.
+    pub fn append_expiring(&mut self, key: &[u8], value: &[u8], expires_at: u64) -> io::Result<()> {
+        let mut marker = [EXPIRE_MARKER; 9];
+        marker[1..].copy_from_slice(&expires_at.to_be_bytes());
+        self.append_marked(key, value, &marker)
+    }
+
+    /// Appends a record followed by marker bytes saying what kind it is.
+    fn append_marked(&mut self, key: &[u8], value: &[u8], marker: &[u8]) -> io::Result<()> {
+        let record = KvRecord {
+            key,
+            value,
+            kind: RecordKind::Put,
+        };
+        let mut serialized = serialize(&record).map_err(io::Error::other)?;
+        serialized.extend_from_slice(marker);
+        self.write_record(&serialized)
+    }
+
+    /// Appends `records` as a single batch record, so replay sees either all
+    /// of them or none.
+    pub fn append_batch(&mut self, records: &[KvRecord<'_>]) -> io::Result<()> {
+        let ops: Vec<BatchOp> = records
+            .iter()
+            .map(|record| {
+                let value = match record.kind {
+                    RecordKind::Expiring(expires_at) => {
+                        Cow::Owned([&expires_at.to_be_bytes()[..], record.value].concat())
+                    }
+                    _ => Cow::Borrowed(record.value),
+                };
+                (kind_id(record.kind), record.key, value)
+            })
+            .collect();
+        let ops = serialize(&ops).map_err(io::Error::other)?;
+        let batch = KvRecord {
+            key: &[],
+            value: &ops,
+            kind: RecordKind::Put,
+        };
+        let mut serialized = serialize(&batch).map_err(io::Error::other)?;
+        serialized.push(BATCH_MARKER);
+        self.write_record(&serialized)
+    }
+
+    /// Writes one already-serialized record with its length prefix.
+    fn write_record(&mut self, serialized: &[u8]) -> io::Result<()> {
         let record_len = serialized.len() as u32;
+        let mut prefix = [0u8; 5];
+        let prefix_len = match self.framing {
+            RecordFraming::Fixed32 => {
+                prefix[..4].copy_from_slice(&record_len.to_be_bytes());
+                4
+            }
+            RecordFraming::Varint => encode_varint(record_len, &mut prefix),
+        };
         // Write length prefix
-        self.file.write_all(&record_len.to_be_bytes())?;
+        self.file.write_all(&prefix[..prefix_len])?;
         // Write the actual record
-        self.file.write_all(&serialized)?;
+        self.file.write_all(serialized)?;
         self.file.flush()?;
+        if self.sync_writes {
+            self.file.sync_data()?;
+        }
+        self.offset += (prefix_len + serialized.len()) as u64;
 
         Ok(())
     }
 
     /// Reads *all* records from the WAL as `KvPair` (raw bytes for key + value).
-    /// On EOF, it returns all records read so far.
+    /// On EOF, it returns all records read so far, including when the log
+    /// ends part-way through a record a crash cut short (see
+    /// [`WalRecoveryMode::TolerateCorruptedTail`]). Corrupt records are an
+    /// error.
+    ///
+    /// Tombstones come back as pairs with an empty value and merge operands as
+    /// pairs holding the operand; use [`Wal::replay`] to tell them apart from puts.
     pub fn read(&self) -> io::Result<Vec<KvPair>> {
-        let file = File::open(&self.location)?;
-        let mut reader = BufReader::new(file);
-
         let mut kv_pairs = Vec::new();
+        self.replay_with(WalRecoveryMode::TolerateCorruptedTail, |record| {
+            kv_pairs.push(record.into())
+        })?;
+        Ok(kv_pairs)
+    }
 
-        loop {
-            // Read the 4-byte length
-            let mut len_buf = [0u8; 4];
-            if let Err(e) = reader.read_exact(&mut len_buf) {
-                // If it's EOF, we're done
-                if e.kind() == io::ErrorKind::UnexpectedEof {
-                    break;
-                } else {
-                    return Err(e);
-                }
-            }
-
-            // Convert length to usize
-            let record_len = u32::from_be_bytes(len_buf) as usize;
-            let mut data = vec![0u8; record_len];
-            reader.read_exact(&mut data)?;
+    /// Decodes every record in order, handing each to `f` as a [`KvRecord`]
+    /// borrowed from a reused read buffer, so replay doesn't allocate per record.
+    /// Batches are handed over one operation at a time.
+    ///
+    /// Any torn or corrupt record is an error (see
+    /// [`WalRecoveryMode::AbsoluteConsistency`]). Returns the number of
+    /// records replayed.
+    pub fn replay<F>(&self, f: F) -> io::Result<u64>
+    where
+        F: FnMut(KvRecord<'_>),
+    {
+        self.replay_with(WalRecoveryMode::AbsoluteConsistency, f)
+    }
 
-            let kv = deserialize(&data).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-            kv_pairs.push(kv);
+    /// Like [`Wal::replay`], but handles torn and corrupt records as `mode`
+    /// says. Records handed to `f` before an error are not rolled back.
+    pub fn replay_with<F>(&self, mode: WalRecoveryMode, mut f: F) -> io::Result<u64>
+    where
+        F: FnMut(KvRecord<'_>),
+    {
+        let mut count = 0;
+        let result = self.for_each_frame(|data| match decode_frame(data, &mut f) {
+            Ok(records) => {
+                count += records;
+                Ok(())
+            }
+            Err(e) if mode == WalRecoveryMode::SkipAnyCorrupted => {
+                warn!("Skipping corrupt WAL record after {} records: {}", count, e);
+                Ok(())
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        });
+
+        let Err(e) = result else {
+            return Ok(count);
+        };
+        // Torn records surface as an unexpected EOF
+        let torn = e.kind() == io::ErrorKind::UnexpectedEof;
+        match mode {
+            WalRecoveryMode::PointInTime => {}
+            WalRecoveryMode::TolerateCorruptedTail | WalRecoveryMode::SkipAnyCorrupted if torn => {}
+            _ => return Err(e),
         }
+        warn!("WAL replay stopped after {} records: {}", count, e);
+        Ok(count)
+    }
 
-        Ok(kv_pairs)
+    /// Decodes the records from byte offset `from` on, which must be where a
+    /// record starts (0 for the first), handing each to `f` like
+    /// [`Wal::replay`]. Stops quietly at a record that's only partly
+    /// written, e.g. one another process is still appending.
+    ///
+    /// Returns the offset just past the last complete record, where the
+    /// next call should start. Corrupt records are an error.
+    pub fn tail<F>(&self, from: u64, mut f: F) -> io::Result<u64>
+    where
+        F: FnMut(KvRecord<'_>),
+    {
+        let header_len = match self.framing {
+            RecordFraming::Fixed32 => 0,
+            _ => HEADER_LEN as u64,
+        };
+        let mut reader = BufReader::new(File::open(&self.location)?);
+        let mut end = reader.seek(SeekFrom::Start(from.max(header_len)))?;
+        let mut data = Vec::new();
+        loop {
+            let record_len = match self.read_len(&mut reader) {
+                Ok(Some(len)) => len,
+                Ok(None) => return Ok(end),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(end),
+                Err(e) => return Err(e),
+            };
+            data.resize(record_len, 0);
+            match reader.read_exact(&mut data) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(end),
+                Err(e) => return Err(e),
+            }
+            decode_frame(&data, &mut f)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            end = reader.stream_position()?;
+        }
     }
 
     /// Returns the raw (serialized) records as `Vec<Vec<u8>>`.
-    /// Each record is just the bincode payload (no 4-byte prefix).
+    /// Each record is just the bincode payload (no length prefix).
     pub fn read_raw(&self) -> io::Result<Vec<Vec<u8>>> {
+        let mut raw_records = Vec::new();
+        self.for_each_frame(|data| {
+            // Store this binary chunk as-is
+            raw_records.push(data.to_vec());
+            Ok(())
+        })?;
+
+        Ok(raw_records)
+    }
+
+    /// Calls `f` with the payload of every record in order, stopping at EOF.
+    fn for_each_frame<F>(&self, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
         let file = File::open(&self.location)?;
         let mut reader = BufReader::new(file);
 
-        let mut raw_records = Vec::new();
-        loop {
-            // Read 4-byte length
-            let mut len_buf = [0; 4];
-            if let Err(e) = reader.read_exact(&mut len_buf) {
+        if self.framing != RecordFraming::Fixed32 {
+            let mut header = [0u8; HEADER_LEN];
+            if let Err(e) = reader.read_exact(&mut header) {
+                // A log that only got as far as a torn header is empty
                 if e.kind() == io::ErrorKind::UnexpectedEof {
-                    break; // stop at EOF
-                } else {
-                    return Err(e);
+                    return Ok(());
                 }
+                return Err(e);
             }
-            let record_len = u32::from_be_bytes(len_buf) as usize;
+        }
 
-            // Read `record_len` bytes
-            let mut data = vec![0; record_len];
+        let mut data = Vec::new();
+        while let Some(record_len) = self.read_len(&mut reader)? {
+            data.resize(record_len, 0);
             reader.read_exact(&mut data)?;
+            f(&data)?;
+        }
 
-            // Store this binary chunk as-is
-            raw_records.push(data);
+        Ok(())
+    }
+
+    /// Reads one length prefix, returning `None` if the log ends before it.
+    /// A log ending part-way through a prefix is an `UnexpectedEof` error.
+    fn read_len(&self, reader: &mut impl Read) -> io::Result<Option<usize>> {
+        match self.framing {
+            RecordFraming::Fixed32 => {
+                let mut len_buf = [0u8; 4];
+                let mut filled = 0;
+                while filled < len_buf.len() {
+                    match reader.read(&mut len_buf[filled..]) {
+                        // If it's EOF before the prefix, we're done
+                        Ok(0) if filled == 0 => return Ok(None),
+                        Ok(0) => return Err(torn_length()),
+                        Ok(n) => filled += n,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(Some(u32::from_be_bytes(len_buf) as usize))
+            }
+            RecordFraming::Varint => {
+                let mut len = 0usize;
+                let mut shift = 0;
+                loop {
+                    let mut byte = [0u8; 1];
+                    match reader.read_exact(&mut byte) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                            return if shift == 0 {
+                                Ok(None)
+                            } else {
+                                Err(torn_length())
+                            };
+                        }
+                        Err(e) => return Err(e),
+                    }
+                    if shift > 28 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "WAL record length overflows a u32",
+                        ));
+                    }
+                    len |= ((byte[0] & 0x7F) as usize) << shift;
+                    if byte[0] & 0x80 == 0 {
+                        return Ok(Some(len));
+                    }
+                    shift += 7;
+                }
+            }
         }
+    }
 
-        Ok(raw_records)
+    /// Copies WAL bytes that haven't been shipped yet into `archive_dir`,
+    /// resuming from where the previous call left off.
+    ///
+    /// The archive holds a copy of the log named after the segment it becomes
+    /// when rotated (see [`segment_path`]), so a rotated log is archived
+    /// afresh while its closed segment can still be shipped to the end. Next
+    /// to each copy a `<name>.watermark` file records how many bytes were
+    /// copied and their CRC-32. The watermark is only advanced after the copy
+    /// is synced, so an interrupted run is simply redone. Returns the new
+    /// watermark offset.
+    pub fn archive(&self, archive_dir: &Path) -> io::Result<u64> {
+        let (copy_path, _) = self.archive_paths(archive_dir)?;
+        archive_file(Path::new(&self.location), self.offset, &copy_path)
+    }
+
+    /// Copies the log as written so far into a new file at `dest` and syncs
+    /// it. Returns the number of bytes copied.
+    pub fn copy_to(&self, dest: &Path) -> io::Result<u64> {
+        let mut copy = OpenOptions::new().write(true).create_new(true).open(dest)?;
+        let mut source = File::open(&self.location)?.take(self.offset);
+        let copied = io::copy(&mut source, &mut copy)?;
+        copy.sync_all()?;
+        Ok(copied)
+    }
+
+    /// Reads the log as written so far.
+    pub fn contents(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.offset as usize);
+        File::open(&self.location)?
+            .take(self.offset)
+            .read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Point-in-time restore: writes the writes up to sequence number
+    /// `until_seq` from the WAL at `source` (e.g. an archived copy of a log
+    /// that was never rotated) into a new WAL at `dest`, so opening a DB on
+    /// `dest` gives the state as of `until_seq`. See
+    /// [`restore_segments_until`] for rotated logs.
+    ///
+    /// Returns the sequence number of the last write restored, which is
+    /// lower than `until_seq` if `source` ends first.
+    pub fn restore_until(source: &str, dest: &str, until_seq: u64) -> io::Result<u64> {
+        restore_segments_until(
+            &[(0, PathBuf::from(source))],
+            Path::new(dest),
+            &[],
+            until_seq,
+        )
+    }
+
+    /// Checks that the archived copy in `archive_dir` matches the checksum
+    /// recorded in its watermark.
+    pub fn verify_archive(&self, archive_dir: &Path) -> io::Result<bool> {
+        let (copy_path, watermark_path) = self.archive_paths(archive_dir)?;
+        let Some((offset, expected)) = read_watermark(&watermark_path)? else {
+            return Ok(false);
+        };
+
+        let mut data = Vec::new();
+        File::open(&copy_path)?
+            .take(offset)
+            .read_to_end(&mut data)?;
+        Ok(data.len() as u64 == offset && crc32(&data) == expected)
+    }
+
+    fn archive_paths(&self, archive_dir: &Path) -> io::Result<(PathBuf, PathBuf)> {
+        let segment = segment_path(Path::new(&self.location), self.base);
+        let name = segment.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "WAL location has no file name")
+        })?;
+        let copy = archive_dir.join(name);
+        Ok((copy.clone(), watermark_path(&copy)))
+    }
+}
+
+/// Works out an existing log's framing from its first bytes.
+fn detect_framing(file: &mut File) -> io::Result<RecordFraming> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    file.take(HEADER_LEN as u64).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if !header.starts_with(&HEADER_MAGIC) {
+        return Ok(RecordFraming::Fixed32);
+    }
+    match header.get(HEADER_MAGIC.len()) {
+        Some(&id) => RecordFraming::from_id(id),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "WAL header is truncated",
+        )),
     }
 }
 
+/// Decodes one frame's payload, passing its records (more than one for a
+/// batch) to `f`. Returns how many there were.
+fn decode_frame<F>(data: &[u8], f: &mut F) -> bincode::Result<u64>
+where
+    F: FnMut(KvRecord<'_>),
+{
+    let mut record: KvRecord = deserialize(data)?;
+    let size = serialized_size(&record)? as usize;
+    match data.get(size) {
+        Some(&DELETE_MARKER) => record.kind = RecordKind::Delete,
+        Some(&MERGE_MARKER) => record.kind = RecordKind::Merge,
+        Some(&APPEND_MARKER) => record.kind = RecordKind::Append,
+        Some(&DELETE_RANGE_MARKER) => record.kind = RecordKind::DeleteRange,
+        Some(&EXPIRE_MARKER) => {
+            let (expires_at, _) = split_expiry(&data[size + 1..])?;
+            record.kind = RecordKind::Expiring(expires_at);
+        }
+        Some(&BATCH_MARKER) => {
+            // Decode the whole batch before applying any of it
+            let ops: Vec<(u8, &[u8], &[u8])> = deserialize(record.value)?;
+            let records = ops
+                .into_iter()
+                .map(|(kind, key, value)| match kind_from_id(kind)? {
+                    RecordKind::Expiring(_) => {
+                        let (expires_at, value) = split_expiry(value)?;
+                        Ok(KvRecord {
+                            key,
+                            value,
+                            kind: RecordKind::Expiring(expires_at),
+                        })
+                    }
+                    kind => Ok(KvRecord { key, value, kind }),
+                })
+                .collect::<bincode::Result<Vec<_>>>()?;
+            let count = records.len() as u64;
+            records.into_iter().for_each(f);
+            return Ok(count);
+        }
+        _ => {}
+    }
+    f(record);
+    Ok(1)
+}
+
+fn kind_id(kind: RecordKind) -> u8 {
+    match kind {
+        RecordKind::Put => 0,
+        RecordKind::Delete => DELETE_MARKER,
+        RecordKind::Merge => MERGE_MARKER,
+        RecordKind::Expiring(_) => EXPIRE_MARKER,
+        RecordKind::Append => APPEND_MARKER,
+        RecordKind::DeleteRange => DELETE_RANGE_MARKER,
+    }
+}
+
+fn kind_from_id(id: u8) -> bincode::Result<RecordKind> {
+    match id {
+        0 => Ok(RecordKind::Put),
+        DELETE_MARKER => Ok(RecordKind::Delete),
+        MERGE_MARKER => Ok(RecordKind::Merge),
+        // The caller fills in the expiry time
+        EXPIRE_MARKER => Ok(RecordKind::Expiring(0)),
+        APPEND_MARKER => Ok(RecordKind::Append),
+        DELETE_RANGE_MARKER => Ok(RecordKind::DeleteRange),
+        _ => Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "unknown record kind {}",
+            id
+        )))),
+    }
+}
+
+/// Splits a big-endian expiry time off the front of `data`.
+fn split_expiry(data: &[u8]) -> bincode::Result<(u64, &[u8])> {
+    let (expires_at, rest) = data.split_first_chunk::<8>().ok_or_else(|| {
+        Box::new(bincode::ErrorKind::Custom(
+            "record ends inside its expiry time".to_string(),
+        ))
+    })?;
+    Ok((u64::from_be_bytes(*expires_at), rest))
+}
+
+fn torn_length() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "WAL ends inside a record length",
+    )
+}
+
+/// Writes `value` as a LEB128 varint into `buf`, returning the bytes used.
+fn encode_varint(mut value: u32, buf: &mut [u8; 5]) -> usize {
+    let mut i = 0;
+    while value >= 0x80 {
+        buf[i] = (value as u8) | 0x80;
+        value >>= 7;
+        i += 1;
+    }
+    buf[i] = value as u8;
+    i + 1
+}
+
+/// Ships the first `end` bytes of the file at `source` to `copy_path`, from
+/// where the `<copy>.watermark` next to it says the last call stopped; see
+/// [`Wal::archive`]. Returns the new watermark offset.
+fn archive_file(source: &Path, end: u64, copy_path: &Path) -> io::Result<u64> {
+    if let Some(dir) = copy_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let watermark_path = watermark_path(copy_path);
+    let (start, mut crc) = read_watermark(&watermark_path)?.unwrap_or((0, 0));
+    if start > end {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "WAL archive is ahead of the WAL",
+        ));
+    }
+
+    let mut copy = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(copy_path)?;
+    if copy.metadata()?.len() < start {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "WAL archive is shorter than its watermark",
+        ));
+    }
+    // Drop anything an interrupted run wrote past the watermark
+    copy.set_len(start)?;
+    copy.seek(SeekFrom::Start(start))?;
+
+    let mut source = File::open(source)?;
+    source.seek(SeekFrom::Start(start))?;
+    let mut remaining = end - start;
+    let mut buf = vec![0u8; 64 * 1024];
+    while remaining > 0 {
+        let n = remaining.min(buf.len() as u64) as usize;
+        source.read_exact(&mut buf[..n])?;
+        copy.write_all(&buf[..n])?;
+        crc = crc32_update(crc, &buf[..n]);
+        remaining -= n as u64;
+    }
+    copy.sync_all()?;
+
+    write_watermark(&watermark_path, end, crc)?;
+    Ok(end)
+}
+
+/// The watermark file recording how much of an archived copy is shipped.
+fn watermark_path(copy_path: &Path) -> PathBuf {
+    let mut path = copy_path.as_os_str().to_os_string();
+    path.push(".watermark");
+    PathBuf::from(path)
+}
+
+/// Reads `(offset, crc)` from an archive watermark, if one exists.
+fn read_watermark(path: &Path) -> io::Result<Option<(u64, u32)>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut parts = contents.split_whitespace();
+    let offset = parts.next().and_then(|p| p.parse().ok());
+    let crc = parts.next().and_then(|p| p.parse().ok());
+    match (offset, crc) {
+        (Some(offset), Some(crc)) => Ok(Some((offset, crc))),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "malformed WAL archive watermark",
+        )),
+    }
+}
+
+/// Atomically replaces the watermark via a synced temp file and a rename.
+fn write_watermark(path: &Path, offset: u64, crc: u32) -> io::Result<()> {
+    let tmp = path.with_extension("watermark.tmp");
+    {
+        let mut f = File::create(&tmp)?;
+        writeln!(f, "{} {}", offset, crc)?;
+        f.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
 // --------------- tests.rs ---------------
 #[cfg(test)]
 mod tests {
-    use super::Wal;
-    use crate::kv::KvPair;
+    use super::{RecordFraming, Wal, WalRecoveryMode};
+    use crate::kv::{KvPair, KvRecord, RecordKind};
 
     use bincode;
     use env_logger::{Builder, Env};
+    use std::fs;
     use std::io::{self, Read, Write};
+    use std::path::Path;
     use std::sync::{Arc, Mutex};
     use std::thread;
     use tempfile::NamedTempFile;
@@ -402,6 +1287,32 @@ mod tests {
         Ok(())
     }
 
+    /// `read` stops cleanly at a log cut off inside a length prefix or a
+    /// record, while `replay` reports it.
+    #[test]
+    fn test_read_tolerates_torn_tail() -> io::Result<()> {
+        init_logger();
+        let temp = NamedTempFile::new()?;
+        let path = temp.path().to_string_lossy().to_string();
+
+        let mut w = Wal::new(path.clone())?;
+        w.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+        let complete = w.current_offset();
+        w.append(KvPair::new(b"b".to_vec(), b"2".to_vec()))?;
+
+        // Inside the second record, then inside its length prefix
+        for cut in [w.current_offset() - 1, complete + 2] {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)?
+                .set_len(cut)?;
+            let w = Wal::new(path.clone())?;
+            assert_eq!(w.read()?, vec![KvPair::new(b"a".to_vec(), b"1".to_vec())]);
+            assert!(w.replay(|_| {}).is_err());
+        }
+        Ok(())
+    }
+
     /// Manually corrupt one of the records in the middle to ensure that only that record fails,
     /// or the whole read fails, depending on your design.
     #[test]
@@ -484,6 +1395,412 @@ mod tests {
         Ok(())
     }
 
+    /// Each recovery mode's handling of a corrupt middle record and a torn tail.
+    #[test]
+    fn test_recovery_modes() -> io::Result<()> {
+        init_logger();
+        let temp = NamedTempFile::new()?;
+        let path = temp.path().to_string_lossy().to_string();
+        let count = |w: &Wal, mode| w.replay_with(mode, |_| {});
+
+        let mut w = Wal::new(path.clone())?;
+        for i in 0..3u8 {
+            w.append(KvPair::new(vec![i], vec![i]))?;
+        }
+
+        // Clean log: every mode reads everything
+        for mode in [
+            WalRecoveryMode::TolerateCorruptedTail,
+            WalRecoveryMode::AbsoluteConsistency,
+            WalRecoveryMode::PointInTime,
+            WalRecoveryMode::SkipAnyCorrupted,
+        ] {
+            assert_eq!(count(&w, mode)?, 3);
+        }
+
+        // Torn tail: a length prefix cut in half
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)?
+            .write_all(&[0, 0])?;
+        assert_eq!(count(&w, WalRecoveryMode::TolerateCorruptedTail)?, 3);
+        assert_eq!(count(&w, WalRecoveryMode::PointInTime)?, 3);
+        assert_eq!(count(&w, WalRecoveryMode::SkipAnyCorrupted)?, 3);
+        assert!(count(&w, WalRecoveryMode::AbsoluteConsistency).is_err());
+
+        // Corrupt the second record's key length so it no longer decodes
+        let mut contents = std::fs::read(&path)?;
+        let first_len = u32::from_be_bytes(contents[..4].try_into().unwrap()) as usize;
+        let second = 4 + first_len + 4;
+        contents[second..second + 8].fill(0xFF);
+        std::fs::write(&path, &contents)?;
+
+        assert_eq!(count(&w, WalRecoveryMode::PointInTime)?, 1);
+        assert_eq!(count(&w, WalRecoveryMode::SkipAnyCorrupted)?, 2);
+        assert!(count(&w, WalRecoveryMode::TolerateCorruptedTail).is_err());
+        assert!(count(&w, WalRecoveryMode::AbsoluteConsistency).is_err());
+
+        Ok(())
+    }
+
+    /// Tombstones replay as deletes but still decode as plain `KvPair`s.
+    #[test]
+    fn test_append_delete() -> io::Result<()> {
+        init_logger();
+        let temp = NamedTempFile::new()?;
+        let path = temp.path().to_string_lossy().to_string();
+
+        let mut w = Wal::new(path)?;
+        w.append(KvPair::new(b"k".to_vec(), b"v".to_vec()))?;
+        w.append_delete(b"k")?;
+        w.append(KvPair::new(b"k".to_vec(), Vec::new()))?;
+        w.append_merge(b"k", b"+1")?;
+        w.append_expiring(b"k", b"v", 1234)?;
+        w.append_suffix(b"k", b"!")?;
+        w.append_delete_range(b"k", b"m")?;
+
+        let mut kinds = Vec::new();
+        w.replay(|record| kinds.push((record.key.to_vec(), record.kind)))?;
+        assert_eq!(
+            kinds,
+            vec![
+                (b"k".to_vec(), RecordKind::Put),
+                (b"k".to_vec(), RecordKind::Delete),
+                (b"k".to_vec(), RecordKind::Put),
+                (b"k".to_vec(), RecordKind::Merge),
+                (b"k".to_vec(), RecordKind::Expiring(1234)),
+                (b"k".to_vec(), RecordKind::Append),
+                (b"k".to_vec(), RecordKind::DeleteRange),
+            ]
+        );
+
+        let raw = w.read_raw()?;
+        let tombstone: KvPair = bincode::deserialize(&raw[1]).unwrap();
+        assert_eq!(tombstone, KvPair::new(b"k".to_vec(), Vec::new()));
+
+        Ok(())
+    }
+
+    /// A batch replays as its individual records, or not at all if torn.
+    #[test]
+    fn test_append_batch() -> io::Result<()> {
+        init_logger();
+        let temp = NamedTempFile::new()?;
+        let path = temp.path().to_string_lossy().to_string();
+        let record = |key: &'static [u8], value: &'static [u8], kind| KvRecord { key, value, kind };
+
+        let mut w = Wal::new(path.clone())?;
+        w.append(KvPair::new(b"before".to_vec(), b"1".to_vec()))?;
+        w.append_batch(&[
+            record(b"a", b"2", RecordKind::Put),
+            record(b"before", b"", RecordKind::Delete),
+            record(b"b", b"3", RecordKind::Merge),
+            record(b"c", b"4", RecordKind::Expiring(u64::MAX)),
+        ])?;
+
+        let mut replayed = Vec::new();
+        let count = w.replay(|r| replayed.push((r.key.to_vec(), r.value.to_vec(), r.kind)))?;
+        assert_eq!(count, 5);
+        assert_eq!(replayed[1], (b"a".to_vec(), b"2".to_vec(), RecordKind::Put));
+        assert_eq!(replayed[2].2, RecordKind::Delete);
+        assert_eq!(replayed[3].2, RecordKind::Merge);
+        assert_eq!(
+            replayed[4],
+            (b"c".to_vec(), b"4".to_vec(), RecordKind::Expiring(u64::MAX))
+        );
+        assert_eq!(w.read_raw()?.len(), 2);
+
+        // Lose the last byte of the batch: none of it replays
+        let len = std::fs::metadata(&path)?.len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len - 1)?;
+        assert_eq!(w.replay_with(WalRecoveryMode::PointInTime, |_| {})?, 1);
+
+        Ok(())
+    }
+
+    /// Replayed records borrow from the read buffer and match what was written.
+    #[test]
+    fn test_replay_borrowed_records() -> io::Result<()> {
+        init_logger();
+        let temp = NamedTempFile::new()?;
+        let path = temp.path().to_string_lossy().to_string();
+
+        let mut w = Wal::new(path.clone())?;
+        w.append(KvPair::new(b"short".to_vec(), b"1".to_vec()))?;
+        w.append(KvPair::new(b"k".to_vec(), vec![7u8; 1000]))?;
+        w.append(KvPair::new(b"last".to_vec(), Vec::new()))?;
+
+        let mut seen = Vec::new();
+        let count = w.replay(|record| seen.push(KvPair::from(record)))?;
+        assert_eq!(count, 3);
+        assert_eq!(seen, w.read()?);
+        assert_eq!(seen[1].value, vec![7u8; 1000]);
+
+        // A raw KvPair record decodes as a KvRecord too
+        let raw = w.read_raw()?;
+        let record: KvRecord = bincode::deserialize(&raw[0]).unwrap();
+        assert_eq!(record.key, b"short");
+        assert_eq!(record.value, b"1");
+
+        Ok(())
+    }
+
+    /// Varint-framed logs round-trip, are smaller, and are detected on reopen.
+    #[test]
+    fn test_varint_framing() -> io::Result<()> {
+        init_logger();
+        let dir = tempfile::tempdir()?;
+        let fixed = dir.path().join("fixed.wal").to_string_lossy().to_string();
+        let varint = dir.path().join("varint.wal").to_string_lossy().to_string();
+
+        let mut wf = Wal::new(fixed.clone())?;
+        let mut wv = Wal::with_framing(varint.clone(), RecordFraming::Varint)?;
+        for i in 0..100u32 {
+            let kv = KvPair::new(i.to_be_bytes().to_vec(), vec![1u8; i as usize * 3]);
+            wf.append(kv.clone())?;
+            wv.append(kv)?;
+        }
+        assert!(wv.current_offset() < wf.current_offset());
+        assert_eq!(wv.current_offset(), std::fs::metadata(&varint)?.len());
+
+        // Reopening without saying which framing picks it up from the header
+        let reopened = Wal::new(varint.clone())?;
+        assert_eq!(reopened.framing(), RecordFraming::Varint);
+        assert_eq!(reopened.read()?, Wal::new(fixed.clone())?.read()?);
+
+        // Asking for the wrong framing on an existing log is refused
+        assert!(Wal::with_framing(fixed, RecordFraming::Varint).is_err());
+
+        Ok(())
+    }
+
+    /// The offset tracks the file length across appends and reopens.
+    #[test]
+    fn test_current_offset() -> io::Result<()> {
+        init_logger();
+        let temp = NamedTempFile::new()?;
+        let path = temp.path().to_string_lossy().to_string();
+
+        let mut w = Wal::new(path.clone())?;
+        assert_eq!(w.current_offset(), 0);
+
+        w.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+        w.append(KvPair::new(b"b".to_vec(), b"2".to_vec()))?;
+        let offset = w.current_offset();
+        assert_eq!(offset, std::fs::metadata(&path)?.len());
+
+        // Reopening picks up where we left off
+        let w = Wal::new(path)?;
+        assert_eq!(w.current_offset(), offset);
+
+        Ok(())
+    }
+
+    /// Resetting empties the log but keeps its header, and appends carry on.
+    #[test]
+    fn test_reset() -> io::Result<()> {
+        init_logger();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("varint.wal").to_string_lossy().to_string();
+
+        let mut w = Wal::with_framing(path.clone(), RecordFraming::Varint)?;
+        let header_len = w.current_offset();
+        w.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+        w.reset()?;
+        assert_eq!(w.current_offset(), header_len);
+        w.append(KvPair::new(b"b".to_vec(), b"2".to_vec()))?;
+
+        let reopened = Wal::new(path)?;
+        assert_eq!(reopened.framing(), RecordFraming::Varint);
+        assert_eq!(
+            reopened.read()?,
+            vec![KvPair::new(b"b".to_vec(), b"2".to_vec())]
+        );
+
+        Ok(())
+    }
+
+    /// Archiving copies only new bytes on each call and the copy verifies.
+    #[test]
+    fn test_tail() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("tail.wal");
+        let location = path.to_str().unwrap().to_string();
+        let mut wal = Wal::with_framing(location.clone(), RecordFraming::Varint)?;
+        wal.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+
+        let reader = Wal::open_read_only(location, None)?;
+        assert_eq!(reader.framing(), RecordFraming::Varint);
+        let mut keys = Vec::new();
+        let end = reader.tail(0, |record| keys.push(record.key.to_vec()))?;
+        assert_eq!(end, wal.current_offset());
+
+        // A record only partly on disk is left for the next call
+        wal.append_delete(b"b")?;
+        let bytes = fs::read(&path)?;
+        fs::write(&path, &bytes[..bytes.len() - 1])?;
+        assert_eq!(
+            reader.tail(end, |record| keys.push(record.key.to_vec()))?,
+            end
+        );
+        fs::write(&path, &bytes)?;
+        let end = reader.tail(end, |record| keys.push(record.key.to_vec()))?;
+        assert_eq!(end, bytes.len() as u64);
+        assert_eq!(keys, vec![b"a".to_vec(), b"b".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_resumes_from_watermark() -> io::Result<()> {
+        init_logger();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("db.wal").to_string_lossy().to_string();
+        let archive_dir = dir.path().join("archive");
+
+        // Copies are named after the segment the log becomes when rotated
+        let copy_path = archive_dir.join("db.wal.00000000000000000000");
+
+        let mut w = Wal::new(path.clone())?;
+        w.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+        assert_eq!(w.archive(&archive_dir)?, w.current_offset());
+
+        w.append(KvPair::new(b"b".to_vec(), b"2".to_vec()))?;
+        // Simulate a run that died after writing but before moving the watermark
+        {
+            let mut f = std::fs::OpenOptions::new().append(true).open(&copy_path)?;
+            f.write_all(b"garbage")?;
+        }
+        assert_eq!(w.archive(&archive_dir)?, w.current_offset());
+
+        assert_eq!(std::fs::read(&copy_path)?, std::fs::read(&path)?);
+        assert!(w.verify_archive(&archive_dir)?);
+
+        // Flipping a byte in the copy is detected
+        let mut copy = std::fs::read(&copy_path)?;
+        copy[5] ^= 0xFF;
+        std::fs::write(&copy_path, copy)?;
+        assert!(!w.verify_archive(&archive_dir)?);
+
+        Ok(())
+    }
+
+    /// Rotating starts a fresh log and archive copy, while the closed segment
+    /// can still be shipped to its end.
+    #[test]
+    fn test_archive_across_rotation() -> io::Result<()> {
+        init_logger();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("db.wal");
+        let archive_dir = dir.path().join("archive");
+
+        let mut w = Wal::with_framing(path.to_string_lossy().to_string(), RecordFraming::Varint)?;
+        w.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+        w.archive(&archive_dir)?;
+        w.append(KvPair::new(b"b".to_vec(), b"2".to_vec()))?;
+
+        let segment = w.rotate(2)?;
+        assert_eq!(segment, super::segment_path(&path, 0));
+        assert_eq!((w.base(), w.framing()), (2, RecordFraming::Varint));
+        assert!(w.read()?.is_empty());
+        w.append(KvPair::new(b"c".to_vec(), b"3".to_vec()))?;
+        w.archive(&archive_dir)?;
+
+        // The closed segment's copy picks up where the live log's left off
+        let len = fs::metadata(&segment)?.len();
+        assert_eq!(super::archive_segment(&segment, &archive_dir)?, len);
+        let archived = |base| {
+            let copy = archive_dir.join(super::segment_path(Path::new("db.wal"), base));
+            Wal::new(copy.to_string_lossy().to_string())?.read()
+        };
+        let keys = |pairs: Vec<KvPair>| pairs.into_iter().map(|kv| kv.key).collect::<Vec<_>>();
+        assert_eq!(keys(archived(0)?), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys(archived(2)?), vec![b"c".to_vec()]);
+
+        // Undoing a rotation puts the segment back
+        let mut w = Wal::new(dir.path().join("undo.wal").to_string_lossy().to_string())?;
+        w.append(KvPair::new(b"a".to_vec(), b"1".to_vec()))?;
+        let segment = w.rotate(1)?;
+        w.undo_rotate(&segment, 0)?;
+        assert_eq!((w.base(), w.read()?.len()), (0, 1));
+        assert!(!segment.exists());
+        Ok(())
+    }
+
+    /// Restoring stops at the requested sequence number.
+    #[test]
+    fn test_restore_until() -> io::Result<()> {
+        init_logger();
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("db.wal").to_string_lossy().to_string();
+        let dest = dir
+            .path()
+            .join("restored.wal")
+            .to_string_lossy()
+            .to_string();
+
+        let mut w = Wal::new(source.clone())?;
+        for i in 0..5 {
+            w.append(KvPair::new(format!("k{}", i).into_bytes(), vec![i]))?;
+        }
+
+        assert_eq!(Wal::restore_until(&source, &dest, 3)?, 3);
+        let restored = Wal::new(dest.clone())?.read()?;
+        assert_eq!(restored.len(), 3);
+        assert_eq!(restored[2].key, b"k2".to_vec());
+
+        // The destination must be fresh
+        assert!(Wal::restore_until(&source, &dest, 3).is_err());
+        // Asking past the end restores everything there is
+        let all = dir.path().join("all.wal").to_string_lossy().to_string();
+        assert_eq!(Wal::restore_until(&source, &all, 100)?, 5);
+
+        Ok(())
+    }
+
+    /// Restoring counts each write in a batch, skips ingested sequence
+    /// numbers and follows the log across rotations.
+    #[test]
+    fn test_restore_counts_sequence_numbers() -> io::Result<()> {
+        init_logger();
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("db.wal");
+        let record = |key: &'static [u8], value: &'static [u8]| KvRecord {
+            key,
+            value,
+            kind: RecordKind::Put,
+        };
+
+        let mut w = Wal::new(path.to_string_lossy().to_string())?;
+        w.append(KvPair::new(b"k1".to_vec(), b"1".to_vec()))?;
+        w.append_batch(&[record(b"a", b"2"), record(b"b", b"3"), record(b"c", b"4")])?;
+        w.append_delete_range(b"a", b"b")?;
+        // An ingested table takes sequence number 6, then a flush rotates
+        let segment = w.rotate(6)?;
+        w.append(KvPair::new(b"k7".to_vec(), b"7".to_vec()))?;
+        w.append(KvPair::new(b"k8".to_vec(), b"8".to_vec()))?;
+
+        let sources = [(0, segment), (6, path.clone())];
+        let restore = |name: &str, sources: &[(u64, std::path::PathBuf)], ingested, until| {
+            let dest = dir.path().join(name);
+            let last = super::restore_segments_until(sources, &dest, ingested, until)?;
+            let restored = Wal::new(dest.to_string_lossy().to_string())?;
+            Ok::<_, io::Error>((last, restored.replay(|_| {})?))
+        };
+        // The batch isn't split to stop at 3
+        assert_eq!(restore("3.wal", &sources, &[6], 3)?, (1, 1));
+        assert_eq!(restore("4.wal", &sources, &[6], 4)?, (4, 4));
+        assert_eq!(restore("7.wal", &sources, &[6], 7)?, (7, 6));
+        assert_eq!(restore("all.wal", &sources, &[6], 100)?, (8, 7));
+
+        // Without knowing about the ingest the second segment doesn't follow
+        // on, so restoring stops before it
+        assert_eq!(restore("gap.wal", &sources, &[], 100)?, (5, 5));
+        Ok(())
+    }
+
     /// Very simplistic concurrency test: multiple threads each append multiple records.
     /// We wrap the single WAL in a Mutex so that writes do not interleave arbitrarily.
     #[test]
@@ -0,0 +1,85 @@
+use crate::kv::{KvRecord, RecordKind};
+
+/// A group of puts and deletes applied atomically by [`DB::write`](crate::db::DB::write).
+///
+/// The batch is written to the WAL as a single record, so after a crash
+/// either every operation in it is replayed or none is.
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    ops: Vec<(RecordKind, Vec<u8>, Vec<u8>)>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.ops.push((RecordKind::Put, key, value));
+    }
+
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.ops.push((RecordKind::Delete, key, Vec::new()));
+    }
+
+    /// Deletes the keys in `[start, end)`, see
+    /// [`DB::delete_range`](crate::db::DB::delete_range). Like that, an
+    /// empty range (`start >= end`) isn't added.
+    pub fn delete_range(&mut self, start: Vec<u8>, end: Vec<u8>) {
+        if start < end {
+            self.ops.push((RecordKind::DeleteRange, start, end));
+        }
+    }
+
+    /// Adds a merge operand for `key`, see [`DB::merge`](crate::db::DB::merge).
+    pub fn merge(&mut self, key: Vec<u8>, operand: Vec<u8>) {
+        self.ops.push((RecordKind::Merge, key, operand));
+    }
+
+    /// Number of operations in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Empties the batch, keeping its allocation for reuse.
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+
+    /// The batch's operations as WAL records, in the order they were added.
+    pub fn records(&self) -> impl Iterator<Item = KvRecord<'_>> {
+        self.ops.iter().map(|(kind, key, value)| KvRecord {
+            key,
+            value,
+            kind: *kind,
+        })
+    }
+
+    pub(crate) fn into_ops(self) -> impl Iterator<Item = (RecordKind, Vec<u8>, Vec<u8>)> {
+        self.ops.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WriteBatch;
+    use crate::kv::RecordKind;
+
+    #[test]
+    fn test_records_keep_order() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"a".to_vec(), b"1".to_vec());
+        batch.delete(b"a".to_vec());
+        assert_eq!(batch.len(), 2);
+
+        let kinds: Vec<RecordKind> = batch.records().map(|r| r.kind).collect();
+        assert_eq!(kinds, vec![RecordKind::Put, RecordKind::Delete]);
+
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+}